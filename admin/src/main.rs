@@ -0,0 +1,130 @@
+use anyhow::{anyhow, Result};
+use futures::prelude::*;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use structopt::StructOpt;
+use tokio::net::UnixStream;
+use tokio_serde::{formats::Bincode, Framed};
+use tokio_util::codec::{Framed as FramedCodec, LengthDelimitedCodec};
+use xenopeltis_common::*;
+
+#[derive(StructOpt, Clone, Debug)]
+struct Options {
+    /// Admin socket of the server.
+    #[structopt(long, short, env = "XENOPELTIS_ADMIN_SOCKET")]
+    socket: PathBuf,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt, Clone, Debug)]
+enum Command {
+    /// List connected players and bots.
+    Players,
+    /// Disconnect a player.
+    Kick { id: PlayerId },
+    /// Disconnect and refuse all players from an address or with a name.
+    Ban { target: BanTarget },
+    /// Lift a ban.
+    Unban { target: BanTarget },
+    /// Set milliseconds between game ticks.
+    Tick { tick: u64 },
+    /// Set amount of food on the board.
+    Food { food: usize },
+    /// Pause the game.
+    Pause,
+    /// Resume the game.
+    Resume,
+    /// Remove all snakes and food and start over.
+    Reset,
+    /// Show a message to all players.
+    Broadcast { text: Vec<String> },
+}
+
+/// Players can be banned by address or by name.
+#[derive(Clone, Debug)]
+enum BanTarget {
+    Address(IpAddr),
+    Name(String),
+}
+
+impl FromStr for BanTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(target: &str) -> Result<Self> {
+        match target.parse() {
+            Ok(address) => Ok(BanTarget::Address(address)),
+            Err(_) if name_valid(target) => Ok(BanTarget::Name(target.into())),
+            Err(_) => Err(anyhow!("Not an address or player name: {}", target)),
+        }
+    }
+}
+
+impl From<Command> for AdminCommand {
+    fn from(command: Command) -> Self {
+        use AdminCommand::{Config, Game};
+        match command {
+            Command::Players => Game(GameCommand::Players),
+            Command::Kick { id } => Game(GameCommand::Kick(id)),
+            Command::Ban { target } => match target {
+                BanTarget::Address(address) => Game(GameCommand::Ban(address)),
+                BanTarget::Name(name) => Game(GameCommand::BanName(name)),
+            },
+            Command::Unban { target } => match target {
+                BanTarget::Address(address) => Game(GameCommand::Unban(address)),
+                BanTarget::Name(name) => Game(GameCommand::UnbanName(name)),
+            },
+            Command::Tick { tick } => Config(ConfigCommand::Tick(tick)),
+            Command::Food { food } => Config(ConfigCommand::Food(food)),
+            Command::Pause => Game(GameCommand::Pause),
+            Command::Resume => Game(GameCommand::Resume),
+            Command::Reset => Game(GameCommand::Reset),
+            Command::Broadcast { text } => Game(GameCommand::Broadcast(text.join(" "))),
+        }
+    }
+}
+
+fn print_players(players: &[PlayerInfo]) {
+    println!(
+        "{:>6}  {:<16}  {:<24}  {:>6}",
+        "ID", "NAME", "ADDRESS", "LENGTH"
+    );
+    for player in players {
+        let address = match player.address {
+            Some(address) => address.to_string(),
+            None => "bot".into(),
+        };
+        let length = match player.length {
+            Some(length) => length.to_string(),
+            None => "-".into(),
+        };
+        let name = player.name.as_deref().unwrap_or("-");
+        println!(
+            "{:>6}  {:<16}  {:<24}  {:>6}",
+            player.id, name, address, length
+        );
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    let options = Options::from_args();
+
+    let stream = UnixStream::connect(&options.socket).await?;
+    let framed_stream = FramedCodec::new(stream, LengthDelimitedCodec::new());
+    let mut framed: Framed<_, AdminResponse, AdminCommand, _> = Framed::new(
+        framed_stream,
+        Bincode::<AdminResponse, AdminCommand>::default(),
+    );
+
+    framed.send(options.command.into()).await?;
+    match framed.try_next().await? {
+        Some(AdminResponse::Done) => {}
+        Some(AdminResponse::Players(players)) => print_players(&players),
+        Some(AdminResponse::Error(error)) => return Err(anyhow!(error)),
+        None => return Err(anyhow!("Server closed connection")),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use anyhow::{anyhow, Context, Result};
+use std::path::PathBuf;
+
+/// Directory for user files of a kind, `$XDG_CONFIG_HOME/xenopeltis/KIND`.
+fn user_dir(kind: &str) -> Result<PathBuf> {
+    let config = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(config) => PathBuf::from(config),
+        None => PathBuf::from(
+            std::env::var_os("HOME").ok_or_else(|| anyhow!("Can't find home directory"))?,
+        )
+        .join(".config"),
+    };
+    Ok(config.join("xenopeltis").join(kind))
+}
+
+/// Directory for data the client keeps, `$XDG_DATA_HOME/xenopeltis`.
+pub fn data_dir() -> Result<PathBuf> {
+    let data = match std::env::var_os("XDG_DATA_HOME") {
+        Some(data) => PathBuf::from(data),
+        None => PathBuf::from(
+            std::env::var_os("HOME").ok_or_else(|| anyhow!("Can't find home directory"))?,
+        )
+        .join(".local")
+        .join("share"),
+    };
+    Ok(data.join("xenopeltis"))
+}
+
+/// Reads a built-in file by name, a user file by name from the config
+/// directory of its kind, or a file by path.
+pub fn load(name: &str, builtin: &[(&str, &str)], kind: &str) -> Result<String> {
+    if let Some((_, data)) = builtin.iter().find(|(builtin, _)| *builtin == name) {
+        return Ok(data.to_string());
+    }
+
+    let path = match name.contains('/') || name.ends_with(".toml") {
+        true => PathBuf::from(name),
+        false => user_dir(kind)?.join(format!("{}.toml", name)),
+    };
+    std::fs::read_to_string(&path).with_context(|| format!("Reading {}", path.display()))
+}
@@ -0,0 +1,312 @@
+use crate::files;
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use termion::event::Key;
+use xenopeltis_common::Direction;
+
+const ARROWS: &str = include_str!("../keymaps/arrows.toml");
+const WASD: &str = include_str!("../keymaps/wasd.toml");
+const HJKL: &str = include_str!("../keymaps/hjkl.toml");
+
+const BUILTIN: [(&str, &str); 3] = [("arrows", ARROWS), ("wasd", WASD), ("hjkl", HJKL)];
+
+/// Something a key does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    Restart,
+    Pause,
+    Quit,
+    Leaderboard,
+    Minimap,
+}
+
+const ACTIONS: [(&str, Action); 9] = [
+    ("up", Action::Up),
+    ("down", Action::Down),
+    ("left", Action::Left),
+    ("right", Action::Right),
+    ("restart", Action::Restart),
+    ("pause", Action::Pause),
+    ("quit", Action::Quit),
+    ("leaderboard", Action::Leaderboard),
+    ("minimap", Action::Minimap),
+];
+
+/// Keys with names, other keys are written as the character they type.
+const KEYS: [(&str, Key); 9] = [
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("space", Key::Char(' ')),
+    ("enter", Key::Char('\n')),
+    ("tab", Key::Char('\t')),
+    ("esc", Key::Esc),
+    ("backspace", Key::Backspace),
+];
+
+impl Action {
+    fn parse(name: &str) -> Result<Self> {
+        ACTIONS
+            .iter()
+            .find(|(action, _)| *action == name)
+            .map(|(_, action)| *action)
+            .ok_or_else(|| anyhow!("Unknown action {}", name))
+    }
+
+    fn name(&self) -> &'static str {
+        ACTIONS
+            .iter()
+            .find(|(_, action)| action == self)
+            .map(|(name, _)| *name)
+            .unwrap()
+    }
+
+    /// Direction to turn to, for the direction actions.
+    pub fn direction(&self) -> Option<Direction> {
+        match self {
+            Action::Up => Some(Direction::Up),
+            Action::Down => Some(Direction::Down),
+            Action::Left => Some(Direction::Left),
+            Action::Right => Some(Direction::Right),
+            _ => None,
+        }
+    }
+}
+
+fn key_parse(name: &str) -> Result<Key> {
+    if let Some((_, key)) = KEYS.iter().find(|(key, _)| *key == name) {
+        return Ok(*key);
+    }
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(Key::Char(c)),
+        _ => Err(anyhow!("Unknown key {}", name)),
+    }
+}
+
+fn key_name(key: Key) -> String {
+    match KEYS.iter().find(|(_, named)| *named == key) {
+        Some((name, _)) => name.to_string(),
+        None => match key {
+            Key::Char(c) => c.to_string(),
+            key => format!("{:?}", key),
+        },
+    }
+}
+
+/// Keymap as written in a file.
+#[derive(Deserialize)]
+struct KeymapFile {
+    /// Built-in keymap to start from.
+    preset: Option<String>,
+    #[serde(flatten)]
+    bindings: BTreeMap<String, Vec<String>>,
+}
+
+/// Keys bound to every action.
+#[derive(Clone, Debug, Default)]
+pub struct Keymap {
+    bindings: BTreeMap<Action, Vec<Key>>,
+}
+
+impl Keymap {
+    /// Loads a built-in keymap by name, a user keymap by name from the
+    /// config directory, or a keymap file by path.
+    pub fn load(keymap: &str) -> Result<Self> {
+        let data = files::load(keymap, &BUILTIN, "keymaps")
+            .with_context(|| format!("Loading keymap {}", keymap))?;
+        let file: KeymapFile =
+            toml::from_str(&data).with_context(|| format!("Parsing keymap {}", keymap))?;
+
+        let mut result = match &file.preset {
+            Some(preset) if BUILTIN.iter().any(|(name, _)| name == preset) => Keymap::load(preset)?,
+            Some(preset) => return Err(anyhow!("Unknown preset {} in {}", preset, keymap)),
+            None => Keymap::default(),
+        };
+        for (action, keys) in &file.bindings {
+            result
+                .set(action, keys)
+                .with_context(|| format!("Parsing keymap {}", keymap))?;
+        }
+        result.validate()?;
+        Ok(result)
+    }
+
+    /// Replaces the keys of an action.
+    fn set<S: AsRef<str>>(&mut self, action: &str, keys: &[S]) -> Result<()> {
+        let keys = keys
+            .iter()
+            .map(|key| key_parse(key.as_ref()))
+            .collect::<Result<_>>()?;
+        self.bindings.insert(Action::parse(action)?, keys);
+        Ok(())
+    }
+
+    /// Applies a binding like `up=w,k` from the command line.
+    pub fn bind(&mut self, binding: &str) -> Result<()> {
+        let (action, keys) = binding
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Binding must look like action=key,key"))?;
+        let keys: Vec<&str> = keys.split(',').collect();
+        self.set(action, &keys)?;
+        self.validate()
+    }
+
+    /// Makes sure no key does two things.
+    fn validate(&self) -> Result<()> {
+        let mut seen = BTreeMap::new();
+        for (action, keys) in &self.bindings {
+            for key in keys {
+                let key = key_name(*key);
+                if let Some(other) = seen.insert(key.clone(), action) {
+                    return Err(anyhow!(
+                        "Key {} is bound to both {} and {}",
+                        key,
+                        other.name(),
+                        action.name()
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes sure a key in the keymaps of several local players does the
+    /// same for all of them and doesn't turn more than one snake.
+    pub fn validate_shared(keymaps: &[Keymap]) -> Result<()> {
+        let mut seen = BTreeMap::new();
+        for (player, keymap) in keymaps.iter().enumerate() {
+            for (action, keys) in &keymap.bindings {
+                for key in keys {
+                    let key = key_name(*key);
+                    let (other_player, other) = match seen.insert(key.clone(), (player, action)) {
+                        Some(other) => other,
+                        None => continue,
+                    };
+                    if other != action || action.direction().is_some() {
+                        return Err(anyhow!(
+                            "Key {} is bound to {} for player {} and {} for player {}",
+                            key,
+                            other.name(),
+                            other_player + 1,
+                            action.name(),
+                            player + 1
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// What a key does, if anything.
+    pub fn action(&self, key: Key) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(_, keys)| keys.contains(&key))
+            .map(|(action, _)| *action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a keymap file and loads it.
+    fn load_file(name: &str, data: &str) -> Result<Keymap> {
+        let path = std::env::temp_dir().join(format!(
+            "xenopeltis-keymap-{}-{}.toml",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, data).unwrap();
+        let keymap = Keymap::load(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        keymap
+    }
+
+    #[test]
+    fn load_builtin() {
+        let keymap = Keymap::load("wasd").unwrap();
+        assert_eq!(keymap.action(Key::Char('w')), Some(Action::Up));
+        assert_eq!(keymap.action(Key::Char(' ')), Some(Action::Restart));
+        assert_eq!(keymap.action(Key::Up), None);
+
+        let keymap = Keymap::load("arrows").unwrap();
+        assert_eq!(keymap.action(Key::Left), Some(Action::Left));
+
+        let keymap = Keymap::load("hjkl").unwrap();
+        assert_eq!(keymap.action(Key::Char('j')), Some(Action::Down));
+    }
+
+    #[test]
+    fn load_unknown() {
+        assert!(Keymap::load("missing/keymap.toml").is_err());
+    }
+
+    #[test]
+    fn load_file_with_preset() {
+        let keymap = load_file("preset", "preset = \"wasd\"\nup = [\"i\", \"up\"]\n").unwrap();
+        assert_eq!(keymap.action(Key::Char('i')), Some(Action::Up));
+        assert_eq!(keymap.action(Key::Up), Some(Action::Up));
+        assert_eq!(keymap.action(Key::Char('w')), None);
+        assert_eq!(keymap.action(Key::Char('s')), Some(Action::Down));
+    }
+
+    #[test]
+    fn load_file_errors() {
+        assert!(load_file("conflict", "preset = \"wasd\"\nquit = [\"w\"]\n").is_err());
+        assert!(load_file("unknown-preset", "preset = \"dvorak\"\n").is_err());
+        assert!(load_file("action", "jump = [\"j\"]\n").is_err());
+        assert!(load_file("key", "up = [\"pageup\"]\n").is_err());
+    }
+
+    #[test]
+    fn bind() {
+        let mut keymap = Keymap::load("wasd").unwrap();
+        keymap.bind("up=k,up").unwrap();
+        assert_eq!(keymap.action(Key::Char('k')), Some(Action::Up));
+        assert_eq!(keymap.action(Key::Up), Some(Action::Up));
+        assert_eq!(keymap.action(Key::Char('w')), None);
+    }
+
+    #[test]
+    fn bind_errors() {
+        let mut keymap = Keymap::load("wasd").unwrap();
+        assert!(keymap.bind("up").is_err());
+        assert!(keymap.bind("jump=j").is_err());
+        assert!(keymap.bind("up=pageup").is_err());
+        assert!(keymap.bind("up=q").is_err());
+    }
+
+    #[test]
+    fn validate_conflicts() {
+        let mut keymap = Keymap::default();
+        keymap.set("up", &["x"]).unwrap();
+        keymap.set("down", &["x"]).unwrap();
+        let error = keymap.validate().unwrap_err().to_string();
+        assert_eq!(error, "Key x is bound to both up and down");
+    }
+
+    #[test]
+    fn validate_shared() {
+        let keymaps = ["wasd", "arrows"].map(|keymap| Keymap::load(keymap).unwrap());
+        Keymap::validate_shared(&keymaps).unwrap();
+
+        let keymaps = ["wasd", "hjkl"].map(|keymap| Keymap::load(keymap).unwrap());
+        let error = Keymap::validate_shared(&keymaps).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "Key l is bound to leaderboard for player 1 and right for player 2"
+        );
+
+        let keymaps = ["wasd", "wasd"].map(|keymap| Keymap::load(keymap).unwrap());
+        assert!(Keymap::validate_shared(&keymaps).is_err());
+    }
+}
@@ -1,55 +1,280 @@
-use anyhow::Result;
+mod files;
+mod keymap;
+mod minimap;
+mod offline;
+mod palette;
+mod theme;
+mod tls;
+mod viewport;
+
+use anyhow::{anyhow, Result};
 use futures::prelude::*;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::io::{stdout, Write};
+use std::path::PathBuf;
+use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
 use structopt::StructOpt;
 use termion::color::*;
 use termion::cursor::Goto;
-use termion::event::Key;
 use termion::raw::IntoRawMode;
 use termion::screen::*;
 use termion_input_tokio::TermReadAsync;
-use tokio::net::{tcp::OwnedReadHalf, TcpStream};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::signal::unix::{signal, SignalKind};
 use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
 use tokio_serde::{formats::SymmetricalBincode, SymmetricallyFramed};
 use tokio_util::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
+use unicode_width::UnicodeWidthStr;
 use xenopeltis_common::Color;
 use xenopeltis_common::*;
 
+use keymap::{Action, Keymap};
+use minimap::Minimap;
+use palette::{color_parse, pattern_parse, segment_color, ColorDepth};
+use theme::Theme;
+use viewport::Viewport;
+
 #[derive(StructOpt, Clone, Debug)]
 pub struct Options {
-    server: String,
+    /// Address of the server, like example.com:8000.
+    #[structopt(required_unless = "offline")]
+    server: Option<String>,
+    /// Name shown to other players and on the leaderboard, defaults to
+    /// your user name.
+    #[structopt(long, short)]
+    name: Option<String>,
+    /// Log in to the account with this name.
+    #[structopt(long, env = "XENOPELTIS_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+    /// Create the account before logging in, needs a password.
+    #[structopt(long, requires = "password")]
+    register: bool,
+    /// Log in with a pre-shared token instead of a password.
+    #[structopt(
+        long,
+        env = "XENOPELTIS_TOKEN",
+        hide_env_values = true,
+        conflicts_with = "password"
+    )]
+    token: Option<String>,
+    /// Connect to the server over TLS.
+    #[structopt(long)]
+    tls: bool,
+    /// Trust server certificates signed by this CA instead of the web roots.
+    #[structopt(long, requires = "tls")]
+    ca: Option<PathBuf>,
+    /// Accept any server certificate, for self-signed certificates.
+    #[structopt(long, requires = "tls")]
+    insecure: bool,
+    /// How to draw the board: emoji, unicode, ascii, the name of a theme in
+    /// ~/.config/xenopeltis/themes or the path of a theme file.
+    #[structopt(long, env = "XENOPELTIS_THEME", default_value = "emoji")]
+    theme: String,
+    /// Colors the terminal can show: truecolor, 256 or 16. Guessed from
+    /// COLORTERM and TERM by default.
+    #[structopt(long, env = "XENOPELTIS_COLORS")]
+    colors: Option<ColorDepth>,
+    /// Color of your snake, like ff8800. The server picks one if not set.
+    #[structopt(long, parse(try_from_str = color_parse))]
+    color: Option<Color>,
+    /// Head of your snake, one or two characters.
+    #[structopt(long)]
+    head: Option<String>,
+    /// Pattern of your snake: solid, striped or gradient.
+    #[structopt(long, default_value = "solid", parse(try_from_str = pattern_parse))]
+    pattern: Pattern,
+    /// Keys to play with: arrows, wasd, hjkl, the name of a keymap in
+    /// ~/.config/xenopeltis/keymaps or the path of a keymap file. Give it
+    /// again for every further player sharing this terminal, who gets its
+    /// own connection. Defaults to arrows.
+    #[structopt(long, number_of_values = 1)]
+    keys: Vec<String>,
+    /// Players sharing this terminal, 1 or 2. Two players play with WASD
+    /// and the arrow keys, unless --keys says otherwise. Only the first
+    /// player logs in, so servers that require it allow just one.
+    #[structopt(long, default_value = "1")]
+    local_players: usize,
+    /// Play without a server, by classic rules. The best score is kept in
+    /// ~/.local/share/xenopeltis.
+    #[structopt(long, conflicts_with_all = &["server", "tls", "password", "token"])]
+    offline: bool,
+    /// Bots to play against offline.
+    #[structopt(long, default_value = "0")]
+    bots: usize,
+    /// Rows of the board offline.
+    #[structopt(long, default_value = "20")]
+    rows: usize,
+    /// Columns of the board offline.
+    #[structopt(long, default_value = "40")]
+    cols: usize,
+    /// Binds keys to an action, like up=w,k. Bindings are for the first
+    /// player unless they start with the number of another one, like 2:up=i.
+    #[structopt(long, number_of_values = 1)]
+    bind: Vec<String>,
+}
+
+/// Connection to the server, either plain TCP or TLS.
+pub trait Stream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<S: AsyncRead + AsyncWrite + Send + Unpin> Stream for S {}
+
+/// Messages from the game, received from a server or the offline game.
+pub type Messages = Pin<Box<dyn futures::Stream<Item = std::io::Result<ServerMessage>> + Send>>;
+
+/// Messages to the game, sent to a server or the offline game.
+pub type Inputs = Pin<Box<dyn Sink<ClientMessage, Error = std::io::Error> + Send>>;
+
+/// Player playing in this terminal, with its own connection.
+#[derive(Default)]
+pub struct Local {
+    name: String,
+    /// Player id, sent by the server when connecting.
+    id: Option<PlayerId>,
+    game_state: PlayerState,
+    /// Why the player lost, shown until it plays again.
+    lost: Option<String>,
+    /// Score while the snake is alive.
+    score: Option<usize>,
 }
 
+#[derive(Default)]
 pub struct State {
+    locals: Vec<Local>,
+    heads: BTreeMap<PlayerId, Coordinate>,
+    /// Size of the board in rows and columns, sent when connecting.
+    size: (usize, usize),
+    /// Skins of the players, as they joined.
+    skins: BTreeMap<PlayerId, Skin>,
     data: BTreeMap<Coordinate, Field>,
     data_dirty: BTreeMap<Coordinate, Field>,
-    game_state: PlayerState,
+    message: Option<String>,
+    message_dirty: bool,
+    pause: Option<PauseReason>,
+    pause_dirty: bool,
+    leaderboard: Option<LeaderboardMessage>,
+    leaderboard_dirty: bool,
+    /// Recent deaths, newest last.
+    kills: VecDeque<String>,
+    kills_dirty: bool,
+    lost_dirty: bool,
+    /// Best score offline, kept across games.
+    best: Option<usize>,
+    /// Best score offline as last saved, below the best one while a snake
+    /// beats it.
+    best_saved: usize,
+    hud_dirty: bool,
+    minimap_hidden: bool,
+    /// Draw everything again, from our copy of the board.
+    redraw: bool,
     exit: bool,
 }
 
 impl State {
-    pub fn new() -> Self {
-        State {
-            data: BTreeMap::new(),
-            data_dirty: BTreeMap::new(),
-            game_state: PlayerState::Playing,
-            exit: false,
+    /// Marks a row of the board for redrawing, after an overlay covered it.
+    fn redraw_row(&mut self, row: usize) {
+        let (_, cols) = self.size;
+        for col in 0..cols {
+            let coordinate = Coordinate::new(row, col);
+            let field = self.data.get(&coordinate).copied();
+            self.data_dirty
+                .entry(coordinate)
+                .or_insert_with(|| field.unwrap_or(Field::Empty));
         }
     }
+
+    /// Raises the best offline score, if this score beats it.
+    fn best_raise(&mut self, score: usize) {
+        if matches!(self.best, Some(best) if score > best) {
+            self.best = Some(score);
+            self.hud_dirty = true;
+        }
+    }
+
+    /// Saves the best offline score if it went up since it was last saved,
+    /// returns the score that was saved.
+    fn best_save(&mut self) -> Result<Option<usize>> {
+        let best = match self.best {
+            Some(best) if best > self.best_saved => best,
+            _ => return Ok(None),
+        };
+        self.best_saved = best;
+        offline::high_score_save(best)?;
+        Ok(Some(best))
+    }
+
+    /// Saves the best offline score, telling the player about it.
+    fn best_save_message(&mut self) {
+        let message = match self.best_save() {
+            Ok(Some(best)) => format!("New high score: {}", best),
+            Ok(None) => return,
+            Err(e) => format!("Error: {:#}", e),
+        };
+        self.message = Some(message);
+        self.message_dirty = true;
+    }
 }
 
-pub async fn handle_stream(state: Arc<Mutex<State>>, reader: OwnedReadHalf) -> Result<()> {
-    let framed_reader = FramedRead::new(reader, LengthDelimitedCodec::new());
-    let mut framed = SymmetricallyFramed::new(
-        framed_reader,
-        SymmetricalBincode::<ServerMessage>::default(),
-    );
+/// Name of the player that died, or of a bot.
+fn death_name(id: PlayerId, name: &Option<String>) -> String {
+    name.clone().unwrap_or_else(|| format!("bot {}", id))
+}
 
+/// Line for the kill feed.
+fn death_feed(death: &PlayerDiedMessage) -> String {
+    let name = death_name(death.id, &death.name);
+    match death.killer {
+        Some(killer) => format!("{} killed {}", death_name(killer, &death.killer_name), name),
+        None => format!("{}: {}", name, death.cause),
+    }
+}
+
+/// Text for the "You lost" overlay.
+fn death_reason(who: &str, death: &PlayerDiedMessage) -> String {
+    match death.killer {
+        Some(killer) => format!(
+            " {} lost: {} of {}, score {} ",
+            who,
+            death.cause,
+            death_name(killer, &death.killer_name),
+            death.score
+        ),
+        None => format!(" {} lost: {}, score {} ", who, death.cause, death.score),
+    }
+}
+
+/// Handles the messages of the connection of a local player. Every
+/// connection gets the events of the game, only those of the first one are
+/// used.
+pub async fn handle_stream(
+    state: Arc<Mutex<State>>,
+    mut messages: Messages,
+    local: usize,
+) -> Result<()> {
     loop {
-        match framed.try_next().await {
+        let message = messages.try_next().await;
+        let shared = matches!(
+            message,
+            Ok(Some(
+                ServerMessage::FieldChange(_)
+                    | ServerMessage::Motd(_)
+                    | ServerMessage::Broadcast(_)
+                    | ServerMessage::Pause(_)
+                    | ServerMessage::Shutdown(_)
+                    | ServerMessage::PlayerDied(_)
+                    | ServerMessage::Heads(_)
+                    | ServerMessage::PlayerJoined(_)
+                    | ServerMessage::Scores(_)
+            ))
+        );
+        if shared && local > 0 {
+            continue;
+        }
+
+        match message {
             Ok(Some(ServerMessage::FieldChange(field_state))) => {
                 let mut state_lock = state.lock().await;
                 state_lock
@@ -58,116 +283,738 @@ pub async fn handle_stream(state: Arc<Mutex<State>>, reader: OwnedReadHalf) -> R
             }
             Ok(Some(ServerMessage::PlayerState(player_state))) => {
                 let mut state_lock = state.lock().await;
-                state_lock.game_state = player_state.state;
+                match (&state_lock.locals[local].game_state, &player_state.state) {
+                    (_, PlayerState::Waiting) => {
+                        state_lock.message = Some("Waiting for room on the board".into());
+                        state_lock.message_dirty = true;
+                    }
+                    // the snake spawned, so the wait is over
+                    (PlayerState::Waiting, PlayerState::Playing) => {
+                        state_lock.message = None;
+                        state_lock.message_dirty = true;
+                    }
+                    _ => {}
+                }
+                let who = match state_lock.locals.len() {
+                    1 => "You".into(),
+                    _ => state_lock.locals[local].name.clone(),
+                };
+                let lost = match (&player_state.state, &player_state.death) {
+                    (PlayerState::Lost, Some(death)) => Some(death_reason(&who, death)),
+                    _ => None,
+                };
+                if lost != state_lock.locals[local].lost {
+                    state_lock.locals[local].lost = lost;
+                    state_lock.lost_dirty = true;
+                }
+                state_lock.locals[local].game_state = player_state.state;
+
+                if let Some(death) = &player_state.death {
+                    state_lock.best_raise(death.score);
+                    state_lock.best_save_message();
+                }
             }
-            _ => {
-                break;
+            Ok(Some(ServerMessage::Motd(MotdMessage { text })))
+            | Ok(Some(ServerMessage::Broadcast(BroadcastMessage { text }))) => {
+                let mut state_lock = state.lock().await;
+                state_lock.message = Some(text);
+                state_lock.message_dirty = true;
+            }
+            Ok(Some(ServerMessage::Pause(pause))) => {
+                let mut state_lock = state.lock().await;
+                state_lock.pause = pause.reason;
+                state_lock.pause_dirty = true;
+            }
+            Ok(Some(ServerMessage::Leaderboard(leaderboard))) => {
+                let mut state_lock = state.lock().await;
+                // ignore answers that arrive after the leaderboard was hidden
+                if state_lock.leaderboard.is_some() {
+                    state_lock.leaderboard = Some(leaderboard);
+                    state_lock.leaderboard_dirty = true;
+                }
+            }
+            Ok(Some(ServerMessage::Auth(AuthMessage::Required))) if local > 0 => {
+                return Err(anyhow!(
+                    "Server requires logging in, which only the first local player does"
+                ));
+            }
+            Ok(Some(ServerMessage::Auth(auth))) => {
+                let mut state_lock = state.lock().await;
+                state_lock.message = Some(match auth {
+                    AuthMessage::Required => "Log in with --password or --token to play".into(),
+                    AuthMessage::LoggedIn(name) => format!("Logged in as {}", name),
+                    AuthMessage::Failed(reason) => format!("Login failed: {}", reason),
+                });
+                state_lock.message_dirty = true;
+            }
+            Ok(Some(ServerMessage::Error(error))) => {
+                let mut state_lock = state.lock().await;
+                state_lock.message = Some(format!("Error: {}", error.message));
+                state_lock.message_dirty = true;
+            }
+            Ok(Some(ServerMessage::Shutdown(shutdown))) => {
+                let mut state_lock = state.lock().await;
+                state_lock.message =
+                    Some(format!("Server shuts down in {} seconds", shutdown.seconds));
+                state_lock.message_dirty = true;
+            }
+            Ok(Some(ServerMessage::Disconnect(disconnect))) => {
+                return Err(anyhow!("Disconnected: {}", disconnect.reason));
+            }
+            Ok(Some(ServerMessage::PlayerDied(death))) => {
+                let mut state_lock = state.lock().await;
+                state_lock.kills.push_back(death_feed(&death));
+                if state_lock.kills.len() > KILL_FEED_ROWS as usize {
+                    state_lock.kills.pop_front();
+                }
+                state_lock.kills_dirty = true;
+            }
+            Ok(Some(ServerMessage::Welcome(welcome))) => {
+                let mut state_lock = state.lock().await;
+                state_lock.locals[local].id = Some(welcome.id);
+                state_lock.size = welcome.size;
             }
+            Ok(Some(ServerMessage::Heads(heads))) => {
+                let mut state_lock = state.lock().await;
+                state_lock.heads = heads.heads.into_iter().collect();
+            }
+            Ok(Some(ServerMessage::PlayerJoined(joined))) => {
+                let mut state_lock = state.lock().await;
+                state_lock.skins.insert(joined.id, joined.skin);
+                state_lock.redraw = true;
+            }
+            Ok(Some(ServerMessage::Scores(scores))) => {
+                let mut state_lock = state.lock().await;
+                let scores: BTreeMap<_, _> = scores.scores.into_iter().collect();
+                for index in 0..state_lock.locals.len() {
+                    let local = &mut state_lock.locals[index];
+                    let score = local.id.and_then(|id| scores.get(&id)).copied();
+                    if score != local.score {
+                        local.score = score;
+                        state_lock.hud_dirty = true;
+                    }
+                    if let Some(score) = score {
+                        state_lock.best_raise(score);
+                    }
+                }
+            }
+            Ok(Some(ServerMessage::Admin(_))) => {}
+            Ok(None) => return Err(anyhow!("Server closed the connection")),
+            Err(e) => return Err(anyhow!("Invalid message from server: {}", e)),
         }
     }
+}
 
-    Ok(())
+/// Rows taken by the leaderboard: title, header and entries.
+const LEADERBOARD_ROWS: u16 = 12;
+
+/// Deaths shown in the kill feed, below the leaderboard.
+const KILL_FEED_ROWS: u16 = 5;
+
+/// Columns taken by the leaderboard and kill feed.
+const PANEL_WIDTH: u16 = 40;
+
+/// Terminal rows below the board, for the view indicator, scores and
+/// messages.
+const STATUS_ROWS: u16 = 3;
+
+pub async fn draw_task(state: Arc<Mutex<State>>, theme: Theme) {
+    draw_task_run(state, theme).await.unwrap();
 }
 
-pub async fn draw_task(state: Arc<Mutex<State>>) {
-    draw_task_run(state).await.unwrap();
+/// Head of a skin, two columns wide. A narrow character is padded.
+fn head_text(head: &str) -> String {
+    match head.width() {
+        1 => format!("{} ", head),
+        _ => head.to_string(),
+    }
 }
 
-fn map_color(color: Color) -> String {
-    match color {
-        Color::Blue => Fg(Blue).to_string(),
-        Color::Cyan => Fg(Cyan).to_string(),
-        Color::Green => Fg(Green).to_string(),
-        Color::Magenta => Fg(Magenta).to_string(),
-        Color::Red => Fg(Red).to_string(),
-        Color::Yellow => Fg(Yellow).to_string(),
+/// Draws a field of the board, if it is in the viewport.
+fn draw_field<W: Write>(
+    screen: &mut W,
+    theme: &Theme,
+    skins: &BTreeMap<PlayerId, Skin>,
+    view: &Viewport,
+    coordinate: Coordinate,
+    field: Field,
+) -> Result<()> {
+    if !view.contains(coordinate) {
+        return Ok(());
+    }
+
+    let glyph = theme.glyph(field);
+    let (color, text) = match field {
+        Field::Snake(segment) => {
+            let skin = skins.get(&segment.id);
+            let pattern = skin.map(|skin| skin.pattern).unwrap_or_default();
+            let color = theme.colors.fg(segment_color(&segment, pattern));
+            let head = skin.and_then(|skin| skin.head.as_ref());
+            match (segment.part, head) {
+                (Part::Head, Some(head)) => (Some(color), head_text(head)),
+                _ => (Some(color), glyph.text.clone()),
+            }
+        }
+        _ => (glyph.color.map(|color| color.fg()), glyph.text.clone()),
+    };
+    let shape = (color, text.as_str());
+    let goto = Goto(
+        2 * (coordinate.col - view.col) as u16 + 1,
+        (coordinate.row - view.row) as u16 + 1,
+    );
+    let reset = Fg(Reset);
+    match shape {
+        (None, s) => write!(screen, "{}{}", goto, s)?,
+        (Some(c), s) => write!(screen, "{}{}{}{}", goto, c, s, reset)?,
     }
+    Ok(())
 }
 
-pub async fn draw_task_run(state: Arc<Mutex<State>>) -> Result<()> {
+pub async fn draw_task_run(state: Arc<Mutex<State>>, theme: Theme) -> Result<()> {
     let mut screen = AlternateScreen::from(stdout().into_raw_mode()?);
     write!(screen, "{}", termion::cursor::Hide)?;
     screen.flush()?;
 
+    let mut resize = signal(SignalKind::window_change())?;
+    let mut view = Viewport::default();
+    let mut redraw = true;
     let mut interval = tokio::time::interval(Duration::from_millis(20));
     loop {
-        interval.tick().await;
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = resize.recv() => redraw = true,
+        }
         let mut state_lock = state.lock().await;
         if state_lock.exit {
             break;
         }
+        redraw |= std::mem::take(&mut state_lock.redraw);
+
+        let dirty = std::mem::take(&mut state_lock.data_dirty);
+        for (coordinate, field) in dirty.iter() {
+            state_lock.data.insert(*coordinate, *field);
+        }
+
+        // fit the board into the terminal, following our head
+        let board = state_lock.size;
+        let (term_cols, term_rows) = termion::terminal_size()?;
+        let space = (
+            term_rows.saturating_sub(STATUS_ROWS).max(1) as usize,
+            (term_cols / 2).max(1) as usize,
+        );
+        let head = state_lock
+            .locals
+            .iter()
+            .find_map(|local| local.id.and_then(|id| state_lock.heads.get(&id)))
+            .copied();
+        let updated = view.update(board, space, head);
+        if updated != view {
+            view = updated;
+            redraw = true;
+        }
+
+        // draw fields, or the whole viewport from our copy of the board
+        if redraw {
+            write!(screen, "{}", termion::clear::All)?;
+            for row in view.row..view.row + view.rows {
+                for col in view.col..view.col + view.cols {
+                    let coordinate = Coordinate::new(row, col);
+                    let field = state_lock.data.get(&coordinate).copied();
+                    draw_field(
+                        &mut screen,
+                        &theme,
+                        &state_lock.skins,
+                        &view,
+                        coordinate,
+                        field.unwrap_or(Field::Empty),
+                    )?;
+                }
+            }
+
+            if view.scrolls(board) {
+                write!(
+                    screen,
+                    "{}Rows {}-{} of {}, columns {}-{} of {}",
+                    Goto(1, view.rows as u16 + 1),
+                    view.row + 1,
+                    view.row + view.rows,
+                    board.0,
+                    view.col + 1,
+                    view.col + view.cols,
+                    board.1
+                )?;
+            }
+
+            state_lock.pause_dirty = true;
+            state_lock.lost_dirty = true;
+            state_lock.hud_dirty = true;
+            state_lock.message_dirty = true;
+            state_lock.leaderboard_dirty = true;
+            state_lock.kills_dirty = true;
+        } else {
+            for (coordinate, field) in dirty.iter() {
+                draw_field(
+                    &mut screen,
+                    &theme,
+                    &state_lock.skins,
+                    &view,
+                    *coordinate,
+                    *field,
+                )?;
+            }
+        }
 
-        // draw dirty fields
-        for (coordinate, field) in std::mem::take(&mut state_lock.data_dirty).iter() {
-            let shape = match field {
-                Field::Empty => (None, "  "),
-                Field::Food(false) => (None, "🍏"),
-                Field::Food(true) => (None, "🍎"),
-                Field::Snake(color) => (Some(map_color(*color)), "██"),
-                Field::Wall => (None, "▒▒"),
+        // draw minimap in the top right corner of the view, for boards that
+        // don't fit
+        if (redraw || !dirty.is_empty())
+            && theme.minimap
+            && !state_lock.minimap_hidden
+            && view.scrolls(board)
+        {
+            let minimap = Minimap::new(&state_lock.data, board, head);
+            let width = minimap.cols() + 2;
+            if 2 * view.cols >= width + 2 && view.rows >= minimap.rows() + 2 {
+                let left = (2 * view.cols - width) as u16 + 1;
+                let border = "─".repeat(minimap.cols());
+                write!(screen, "{}┌{}┐", Goto(left, 1), border)?;
+                for (row, line) in minimap.lines().iter().enumerate() {
+                    write!(screen, "{}│{}│", Goto(left, row as u16 + 2), line)?;
+                }
+                write!(
+                    screen,
+                    "{}└{}┘",
+                    Goto(left, minimap.rows() as u16 + 2),
+                    border
+                )?;
+            }
+        }
+        redraw = false;
+
+        // draw pause overlay in the middle of the view, on top of any fields
+        let (rows, cols) = (view.rows, view.cols);
+        if state_lock.pause_dirty || (state_lock.pause.is_some() && !dirty.is_empty()) {
+            let text = match state_lock.pause {
+                Some(PauseReason::Admin) => " PAUSED by admin ",
+                Some(PauseReason::Vote) => " PAUSED by vote ",
+                Some(PauseReason::Empty) => " PAUSED ",
+                None => "",
             };
-            let goto = Goto(2 * coordinate.col as u16 + 1, coordinate.row as u16 + 1);
-            let reset = Fg(Reset);
-            match shape {
-                (None, s) => write!(screen, "{}{}", goto, s)?,
-                (Some(c), s) => write!(screen, "{}{}{}{}", goto, c, s, reset)?,
+            let col = (2 * cols).saturating_sub(text.len()) / 2 + 1;
+            let goto = Goto(col as u16, rows as u16 / 2 + 1);
+            write!(screen, "{}{}{}", goto, termion::style::Invert, text)?;
+            write!(screen, "{}", termion::style::Reset)?;
+
+            // when resuming, redraw the fields that were covered
+            if state_lock.pause.is_none() {
+                state_lock.redraw_row(view.row + rows / 2);
             }
-            state_lock.data.insert(*coordinate, *field);
+            state_lock.pause_dirty = false;
+        }
+
+        // draw why players lost below the pause overlay
+        let lost = state_lock.locals.iter().any(|local| local.lost.is_some());
+        if state_lock.lost_dirty || (lost && !dirty.is_empty()) {
+            for index in 0..state_lock.locals.len() {
+                let row = rows / 2 + 1 + index;
+                match &state_lock.locals[index].lost {
+                    _ if row >= rows => {}
+                    Some(text) => {
+                        let col = (2 * cols).saturating_sub(text.chars().count()) / 2 + 1;
+                        let goto = Goto(col as u16, row as u16 + 1);
+                        write!(screen, "{}{}{}", goto, termion::style::Invert, text)?;
+                        write!(screen, "{}", termion::style::Reset)?;
+                    }
+                    None => state_lock.redraw_row(view.row + row),
+                }
+            }
+            state_lock.lost_dirty = false;
+        }
+
+        // draw scores of the players in this terminal below the board
+        if state_lock.hud_dirty {
+            let mut scores: Vec<String> = state_lock
+                .locals
+                .iter()
+                .map(|local| match local.score {
+                    Some(score) => format!("{} {}", local.name, score),
+                    None => format!("{} -", local.name),
+                })
+                .collect();
+            if let Some(best) = state_lock.best {
+                scores.push(format!("best {}", best));
+            }
+            let goto = Goto(1, rows as u16 + 2);
+            write!(
+                screen,
+                "{}{}{}",
+                goto,
+                termion::clear::CurrentLine,
+                scores.join("   ")
+            )?;
+            state_lock.hud_dirty = false;
+        }
+
+        // draw server message below the board
+        if state_lock.message_dirty {
+            let goto = Goto(1, rows as u16 + 3);
+            let message = state_lock.message.as_deref().unwrap_or("");
+            write!(screen, "{}{}{}", goto, termion::clear::CurrentLine, message)?;
+            state_lock.message_dirty = false;
+        }
+
+        // draw leaderboard and kill feed to the right of the board, if they fit
+        let left = 2 * cols as u16 + 3;
+        let panel = left + PANEL_WIDTH <= term_cols + 1;
+        if state_lock.leaderboard_dirty && panel {
+            for row in 0..LEADERBOARD_ROWS {
+                write!(
+                    screen,
+                    "{}{}",
+                    Goto(left, row + 1),
+                    termion::clear::UntilNewline
+                )?;
+            }
+            if let Some(leaderboard) = &state_lock.leaderboard {
+                let title = match leaderboard.period {
+                    LeaderboardPeriod::AllTime => "All time",
+                    LeaderboardPeriod::Weekly => "This week",
+                };
+                write!(screen, "{}{}", Goto(left, 1), title)?;
+                write!(
+                    screen,
+                    "{}{:<16} {:>6} {:>5} {:>4} {:>5}",
+                    Goto(left, 2),
+                    "NAME",
+                    "LENGTH",
+                    "KILLS",
+                    "WINS",
+                    "GAMES"
+                )?;
+                for (row, entry) in leaderboard.entries.iter().enumerate() {
+                    write!(
+                        screen,
+                        "{}{:<16} {:>6} {:>5} {:>4} {:>5}",
+                        Goto(left, row as u16 + 3),
+                        entry.name,
+                        entry.best_length,
+                        entry.kills,
+                        entry.wins,
+                        entry.games
+                    )?;
+                }
+            }
+            state_lock.leaderboard_dirty = false;
+        }
+
+        // draw kill feed below the leaderboard
+        if state_lock.kills_dirty && panel {
+            let top = LEADERBOARD_ROWS + 2;
+            for row in 0..KILL_FEED_ROWS {
+                write!(
+                    screen,
+                    "{}{}",
+                    Goto(left, top + row),
+                    termion::clear::UntilNewline
+                )?;
+            }
+            for (row, kill) in state_lock.kills.iter().enumerate() {
+                write!(screen, "{}{}", Goto(left, top + row as u16), kill)?;
+            }
+            state_lock.kills_dirty = false;
         }
 
         screen.flush()?;
     }
 
     write!(screen, "{}", termion::cursor::Show)?;
+
+    // leaving the alternate screen happens on drop, which doesn't flush
+    drop(screen);
+    stdout().flush()?;
     Ok(())
 }
 
-#[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<()> {
-    let options = Options::from_args();
+fn main() -> Result<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let result = runtime.block_on(run(Options::from_args()));
+
+    // stdin is read on a blocking thread, which only finishes once a key is
+    // pressed, so don't wait for it
+    runtime.shutdown_background();
+    result
+}
+
+/// Name to play as when none is given, the user name without the characters
+/// a player name can't have.
+fn name_default() -> String {
+    let name: String = std::env::var("USER")
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || *c == '_' || *c == '-')
+        .take(NAME_LENGTH_MAX)
+        .collect();
+    match name.is_empty() {
+        true => "player".into(),
+        false => name,
+    }
+}
+
+async fn run(options: Options) -> Result<()> {
+    let name = match &options.name {
+        Some(name) if !name_valid(name) => {
+            return Err(anyhow!(
+                "Name must be 1 to {} letters, digits, '_' or '-'",
+                NAME_LENGTH_MAX
+            ));
+        }
+        Some(name) => name.clone(),
+        None => name_default(),
+    };
+    let password_length = options.password.as_deref().unwrap_or("").chars().count();
+    if options.register && password_length < PASSWORD_LENGTH_MIN {
+        return Err(anyhow!(
+            "Password must have at least {} characters",
+            PASSWORD_LENGTH_MIN
+        ));
+    }
+
+    let skin = Skin {
+        color: options.color,
+        head: options.head.clone(),
+        pattern: options.pattern,
+    };
+    if !skin.valid() {
+        return Err(anyhow!(
+            "Head must be one or two visible characters, color must not be too dark"
+        ));
+    }
+
+    let keys = match (options.keys.len(), options.local_players) {
+        (_, players) if !(1..=2).contains(&players) => {
+            return Err(anyhow!("There can be 1 or 2 local players"));
+        }
+        (0, 1) => vec!["arrows".into()],
+        (0, _) => vec!["wasd".into(), "arrows".into()],
+        (_, 1) => options.keys.clone(),
+        (keys, players) if keys == players => options.keys.clone(),
+        _ => return Err(anyhow!("Give --keys once for every local player")),
+    };
+    let mut keymaps = keys
+        .iter()
+        .map(|keys| Keymap::load(keys))
+        .collect::<Result<Vec<_>>>()?;
+    for binding in &options.bind {
+        let (player, binding): (usize, _) = binding
+            .split_once(':')
+            .and_then(|(player, binding)| Some((player.parse().ok()?, binding)))
+            .unwrap_or((1, binding.as_str()));
+        let keymap = match player {
+            0 => None,
+            player => keymaps.get_mut(player - 1),
+        };
+        keymap
+            .ok_or_else(|| anyhow!("There is no local player {} to bind keys for", player))?
+            .bind(binding)?;
+    }
+    Keymap::validate_shared(&keymaps)?;
+
+    let mut theme = Theme::load(&options.theme)?;
+    theme.colors = options.colors.unwrap_or_else(ColorDepth::detect);
+
+    // further local players are named after the first one
+    let names: Vec<String> = (0..keymaps.len())
+        .map(|index| match index {
+            0 => name.clone(),
+            _ => {
+                let suffix = (index + 1).to_string();
+                let name: String = name.chars().take(NAME_LENGTH_MAX - suffix.len()).collect();
+                name + &suffix
+            }
+        })
+        .collect();
+
+    let best = options.offline.then(offline::high_score);
+    let state = Arc::new(Mutex::new(State {
+        locals: names
+            .iter()
+            .map(|name| Local {
+                name: name.clone(),
+                ..Local::default()
+            })
+            .collect(),
+        best,
+        best_saved: best.unwrap_or(0),
+        ..State::default()
+    }));
 
-    let state = Arc::new(Mutex::new(State::new()));
-    let stream = TcpStream::connect(options.server).await?;
+    let connections = match options.offline {
+        true => offline::start((options.rows, options.cols), options.bots, keymaps.len())?,
+        false => {
+            let mut connections = vec![];
+            for _ in 0..keymaps.len() {
+                connections.push(connect(&options).await?);
+            }
+            connections
+        }
+    };
 
-    let (reader, writer) = stream.into_split();
-    tokio::spawn(handle_stream(state.clone(), reader));
+    let mut players = vec![];
+    let mut stream_tasks = vec![];
+    let locals = keymaps.into_iter().zip(names).zip(connections);
+    for (index, ((keymap, name), (messages, mut inputs))) in locals.enumerate() {
+        stream_tasks.push(tokio::spawn(handle_stream(state.clone(), messages, index)));
+
+        inputs
+            .send(ClientMessage::Hello(HelloMessage {
+                name: name.clone(),
+                // only the first player has a skin and logs in
+                skin: match index {
+                    0 => skin.clone(),
+                    _ => Skin::default(),
+                },
+            }))
+            .await?;
+
+        let auth = match (index, &options.token, &options.password) {
+            (0, Some(token), _) => Some(ClientMessage::Token(TokenMessage {
+                token: token.clone(),
+            })),
+            (0, None, Some(password)) => {
+                let credentials = CredentialsMessage {
+                    name,
+                    password: password.clone(),
+                };
+                match options.register {
+                    true => Some(ClientMessage::Register(credentials)),
+                    false => Some(ClientMessage::Login(credentials)),
+                }
+            }
+            _ => None,
+        };
+        if let Some(auth) = auth {
+            inputs.send(auth).await?;
+        }
+
+        players.push((keymap, inputs));
+    }
+
+    let draw_task = tokio::spawn(draw_task(state.clone(), theme));
+    let result = handle_keys(&state, &mut players, stream_tasks).await;
+
+    // a snake still playing may have beaten the best score
+    let mut state_lock = state.lock().await;
+    let saved = state_lock.best_save();
+    // restore the terminal before reporting why we stopped
+    state_lock.exit = true;
+    drop(state_lock);
+    let _ = draw_task.await;
 
-    let framed_writer = FramedWrite::new(writer, LengthDelimitedCodec::new());
-    let mut framed = SymmetricallyFramed::new(
-        framed_writer,
+    result.and(saved.map(|_| ()))
+}
+
+/// Opens a connection to the server.
+async fn connect(options: &Options) -> Result<(Messages, Inputs)> {
+    let server = options.server.as_deref().unwrap_or_default();
+    let stream = TcpStream::connect(server).await?;
+    let stream: Box<dyn Stream> = match options.tls {
+        true => {
+            let connector = tls::connector(options.ca.as_deref(), options.insecure)?;
+            let name = tls::server_name(server)?;
+            Box::new(connector.connect(name, stream).await?)
+        }
+        false => Box::new(stream),
+    };
+
+    let (reader, writer) = tokio::io::split(stream);
+    let messages = SymmetricallyFramed::new(
+        FramedRead::new(reader, LengthDelimitedCodec::new()),
+        SymmetricalBincode::<ServerMessage>::default(),
+    );
+    let inputs = SymmetricallyFramed::new(
+        FramedWrite::new(writer, LengthDelimitedCodec::new()),
         SymmetricalBincode::<ClientMessage>::default(),
     );
+    Ok((Box::pin(messages), Box::pin(inputs)))
+}
 
-    let draw_task = tokio::spawn(draw_task(state.clone()));
-
+/// Sends the pressed keys to the server, on the connection of the player
+/// whose keymap has them, until a player quits or a connection ends.
+async fn handle_keys<W: Sink<ClientMessage, Error = std::io::Error> + Unpin>(
+    state: &Mutex<State>,
+    players: &mut [(Keymap, W)],
+    mut stream_tasks: Vec<JoinHandle<Result<()>>>,
+) -> Result<()> {
     let mut keys = tokio::io::stdin().keys_stream();
     loop {
-        let key = keys.try_next().await?.unwrap();
-        match key {
-            Key::Char('q') => {
-                let mut state_lock = state.lock().await;
-                state_lock.exit = true;
-                break;
-            }
-            Key::Char('r') | Key::Char(' ') => framed.send(ClientMessage::Restart).await?,
-            Key::Left | Key::Right | Key::Up | Key::Down => {
+        let key = tokio::select! {
+            key = keys.try_next() => match key? {
+                Some(key) => key,
+                None => return Ok(()),
+            },
+            (result, _, _) = future::select_all(stream_tasks.iter_mut()) => return result?,
+        };
+
+        // keys for the terminal only do something once
+        let mut done = vec![];
+        for (index, (keymap, framed)) in players.iter_mut().enumerate() {
+            let action = match keymap.action(key) {
+                Some(action) => action,
+                None => continue,
+            };
+            if let Some(direction) = action.direction() {
                 framed
-                    .send(ClientMessage::Direction(DirectionMessage {
-                        direction: match key {
-                            Key::Left => Direction::Left,
-                            Key::Right => Direction::Right,
-                            Key::Up => Direction::Up,
-                            Key::Down => Direction::Down,
-                            _ => unreachable!(),
-                        },
-                    }))
+                    .send(ClientMessage::Direction(DirectionMessage { direction }))
                     .await?;
+                continue;
+            }
+            if done.contains(&action) {
+                continue;
+            }
+            match action {
+                Action::Restart => {
+                    // one player restarts per key press, the first one not playing
+                    let playing = matches!(
+                        state.lock().await.locals[index].game_state,
+                        PlayerState::Playing | PlayerState::Waiting
+                    );
+                    if playing {
+                        continue;
+                    }
+                    framed.send(ClientMessage::Restart).await?;
+                    state.lock().await.best_save_message();
+                }
+                Action::Pause => framed.send(ClientMessage::Pause).await?,
+                Action::Quit => return Ok(()),
+                Action::Minimap => {
+                    let mut state_lock = state.lock().await;
+                    state_lock.minimap_hidden = !state_lock.minimap_hidden;
+                    state_lock.redraw = true;
+                }
+                Action::Leaderboard => {
+                    // cycle through all time, weekly and hidden
+                    let mut state_lock = state.lock().await;
+                    let period = match &state_lock.leaderboard {
+                        None => Some(LeaderboardPeriod::AllTime),
+                        Some(leaderboard) if leaderboard.period == LeaderboardPeriod::AllTime => {
+                            Some(LeaderboardPeriod::Weekly)
+                        }
+                        Some(_) => None,
+                    };
+                    state_lock.leaderboard = period.map(|period| LeaderboardMessage {
+                        period,
+                        entries: vec![],
+                    });
+                    state_lock.leaderboard_dirty = true;
+                    drop(state_lock);
+                    if let Some(period) = period {
+                        framed.send(ClientMessage::Leaderboard(period)).await?;
+                    }
+                }
+                _ => {}
             }
-            _ => {}
+            done.push(action);
         }
     }
-
-    let _ = draw_task.await;
-
-    Ok(())
 }
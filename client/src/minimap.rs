@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use termion::color::*;
+use xenopeltis_common::{Coordinate, Field};
+
+/// Largest minimap, in terminal cells.
+const MINIMAP_COLS: usize = 24;
+const MINIMAP_ROWS: usize = 8;
+
+/// Braille dots in a character are two wide and four high, this is the bit
+/// of every dot by row and column.
+const DOTS: [[u8; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+#[derive(Clone, Copy, Default)]
+struct Cell {
+    dots: u8,
+    snake: bool,
+    head: bool,
+}
+
+/// Downscaled board, drawn with braille characters.
+pub struct Minimap {
+    cells: Vec<Vec<Cell>>,
+}
+
+impl Minimap {
+    /// Builds the minimap from our copy of the board, showing walls and
+    /// snakes, with our head highlighted.
+    pub fn new(
+        data: &BTreeMap<Coordinate, Field>,
+        (board_rows, board_cols): (usize, usize),
+        head: Option<Coordinate>,
+    ) -> Self {
+        // fields per dot, the same in both directions to keep the shape
+        let scale = [
+            board_cols.div_ceil(2 * MINIMAP_COLS),
+            board_rows.div_ceil(4 * MINIMAP_ROWS),
+            1,
+        ]
+        .into_iter()
+        .max()
+        .unwrap();
+        let rows = board_rows.div_ceil(4 * scale);
+        let cols = board_cols.div_ceil(2 * scale);
+
+        let mut cells = vec![vec![Cell::default(); cols]; rows];
+        for (coordinate, field) in data {
+            if !matches!(field, Field::Wall | Field::Snake(_)) {
+                continue;
+            }
+            let (row, col) = (coordinate.row / scale, coordinate.col / scale);
+            let cell = &mut cells[row / 4][col / 2];
+            cell.dots |= DOTS[row % 4][col % 2];
+            cell.snake |= matches!(field, Field::Snake(_));
+        }
+        if let Some(head) = head {
+            let (row, col) = (head.row / scale, head.col / scale);
+            if let Some(cell) = cells
+                .get_mut(row / 4)
+                .and_then(|cells| cells.get_mut(col / 2))
+            {
+                cell.dots |= DOTS[row % 4][col % 2];
+                cell.head = true;
+            }
+        }
+
+        Minimap { cells }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cells.first().map(|row| row.len()).unwrap_or(0)
+    }
+
+    /// Lines of the minimap, with colors.
+    pub fn lines(&self) -> Vec<String> {
+        self.cells
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| {
+                        let dots = char::from_u32(0x2800 + cell.dots as u32).unwrap();
+                        if cell.head {
+                            format!("{}{}{}", Fg(LightRed), dots, Fg(Reset))
+                        } else if cell.snake {
+                            format!("{}{}{}", Fg(LightGreen), dots, Fg(Reset))
+                        } else {
+                            dots.to_string()
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xenopeltis_common::{Color, Part, Segment};
+
+    fn snake() -> Field {
+        Field::Snake(Segment {
+            id: 1,
+            color: Color::new(200, 100, 0),
+            part: Part::Body,
+            number: 0,
+        })
+    }
+
+    #[test]
+    fn size_keeps_shape() {
+        let small = Minimap::new(&BTreeMap::new(), (4, 4), None);
+        assert_eq!((small.rows(), small.cols()), (1, 2));
+
+        // nine fields per dot in both directions
+        let large = Minimap::new(&BTreeMap::new(), (200, 400), None);
+        assert_eq!((large.rows(), large.cols()), (6, 23));
+    }
+
+    #[test]
+    fn lines_show_walls_snakes_and_head() {
+        let data = [
+            (Coordinate::new(0, 0), Field::Wall),
+            (Coordinate::new(1, 0), Field::Food(false)),
+            (Coordinate::new(3, 1), snake()),
+            (Coordinate::new(0, 2), Field::Wall),
+        ]
+        .into_iter()
+        .collect();
+        let minimap = Minimap::new(&data, (4, 6), Some(Coordinate::new(2, 5)));
+
+        let lines = minimap.lines();
+        assert_eq!(lines.len(), 1);
+        // food is left out, snakes and our head are colored
+        let snake = format!("{}\u{2881}{}", Fg(LightGreen), Fg(Reset));
+        let head = format!("{}\u{2820}{}", Fg(LightRed), Fg(Reset));
+        assert_eq!(lines[0], format!("{}\u{2801}{}", snake, head));
+    }
+}
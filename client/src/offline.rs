@@ -0,0 +1,151 @@
+use crate::{files, Inputs, Messages};
+use anyhow::{Context, Result};
+use futures::prelude::*;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use xenopeltis_common::*;
+use xenopeltis_game::{mode, Game, Map};
+
+/// Milliseconds between game ticks, like on a server with default settings.
+const TICK: u64 = 100;
+
+/// Amount of food on the board.
+const FOOD: usize = 2;
+
+/// Starts a game with classic rules in this process, for playing without a
+/// server. Returns the messages and inputs of every local player, like a
+/// connection to a server would.
+pub fn start(
+    (rows, cols): (usize, usize),
+    bots: usize,
+    players: usize,
+) -> Result<Vec<(Messages, Inputs)>> {
+    let mut game = Game::new(Map::new(rows, cols)?, mode::mode("classic")?);
+    game.food_set(FOOD);
+
+    let address = SocketAddr::from(([127, 0, 0, 1], 0));
+    let (sender, inputs) = unbounded_channel();
+    let mut connections = vec![];
+    for _ in 0..players {
+        let (id, events, private) = game.client_add(address)?;
+        game.player_add(id);
+
+        let messages = stream::iter(game.messages_initial(id))
+            .chain(stream::select(
+                events_stream(events),
+                private_stream(private),
+            ))
+            .map(Ok);
+        let sender = sender.clone();
+        let inputs = sink::unfold((), move |(), message| {
+            // the game only stops once all inputs are gone
+            let _ = sender.send((id, message));
+            future::ready(Ok(()))
+        });
+        connections.push((Box::pin(messages) as Messages, Box::pin(inputs) as Inputs));
+    }
+    game.bots_set(bots);
+
+    tokio::spawn(game_loop(game, inputs));
+    Ok(connections)
+}
+
+fn events_stream(events: Receiver<ServerMessage>) -> impl Stream<Item = ServerMessage> {
+    stream::unfold(events, |mut events| async move {
+        loop {
+            match events.recv().await {
+                Ok(message) => return Some((message, events)),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+fn private_stream(private: UnboundedReceiver<ServerMessage>) -> impl Stream<Item = ServerMessage> {
+    stream::unfold(private, |mut private| async move {
+        private.recv().await.map(|message| (message, private))
+    })
+}
+
+/// Runs the game, applying inputs at tick boundaries like the server does.
+async fn game_loop(mut game: Game, mut inputs: UnboundedReceiver<(PlayerId, ClientMessage)>) {
+    let mut interval = tokio::time::interval(Duration::from_millis(TICK));
+    let mut pending = vec![];
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                for (id, message) in pending.drain(..) {
+                    match message {
+                        ClientMessage::Leaderboard(_) => game.error(
+                            id,
+                            ErrorCode::Unavailable,
+                            "There is no leaderboard offline",
+                        ),
+                        message => game.handle(id, &message),
+                    }
+                }
+
+                if !game.paused() {
+                    game.tick();
+                }
+            }
+            input = inputs.recv() => match input {
+                Some(input) => pending.push(input),
+                None => break,
+            },
+        }
+    }
+}
+
+fn high_score_path() -> Result<PathBuf> {
+    Ok(files::data_dir()?.join("highscore"))
+}
+
+/// Best score reached offline, stored in `$XDG_DATA_HOME/xenopeltis`.
+pub fn high_score() -> usize {
+    high_score_path()
+        .map(|path| high_score_read(&path))
+        .unwrap_or(0)
+}
+
+pub fn high_score_save(score: usize) -> Result<()> {
+    high_score_write(&high_score_path()?, score)
+}
+
+fn high_score_read(path: &Path) -> usize {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|data| data.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+fn high_score_write(path: &Path, score: usize) -> Result<()> {
+    path.parent()
+        .map(std::fs::create_dir_all)
+        .unwrap_or(Ok(()))
+        .and_then(|_| std::fs::write(path, format!("{}\n", score)))
+        .with_context(|| format!("Saving high score in {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn high_score_load_save() {
+        let dir = std::env::temp_dir().join(format!("xenopeltis-data-{}", std::process::id()));
+        let path = dir.join("highscore");
+        assert_eq!(high_score_read(&path), 0);
+
+        high_score_write(&path, 42).unwrap();
+        assert_eq!(high_score_read(&path), 42);
+
+        std::fs::write(&path, "lots").unwrap();
+        assert_eq!(high_score_read(&path), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
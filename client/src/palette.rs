@@ -0,0 +1,257 @@
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+use termion::color::{AnsiValue, Fg, Rgb};
+use xenopeltis_common::{Color, Part, Pattern, Segment};
+
+/// Levels of the color cube of 256-color terminals.
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// Colors of 16-color terminals as xterm draws them, by color number,
+/// without black which would be invisible.
+const ANSI: [Color; 15] = [
+    Color::new(205, 0, 0),
+    Color::new(0, 205, 0),
+    Color::new(205, 205, 0),
+    Color::new(0, 0, 238),
+    Color::new(205, 0, 205),
+    Color::new(0, 205, 205),
+    Color::new(229, 229, 229),
+    Color::new(127, 127, 127),
+    Color::new(255, 0, 0),
+    Color::new(0, 255, 0),
+    Color::new(255, 255, 0),
+    Color::new(92, 92, 255),
+    Color::new(255, 0, 255),
+    Color::new(0, 255, 255),
+    Color::new(255, 255, 255),
+];
+
+/// Segments of a gradient, from light to dark and back.
+const GRADIENT: u32 = 12;
+
+/// Parses a color like `ff8800` or `#ff8800`.
+pub fn color_parse(s: &str) -> Result<Color> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(anyhow!("Color must be six hex digits, like ff8800"));
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+    Ok(Color::new(channel(0)?, channel(2)?, channel(4)?))
+}
+
+pub fn pattern_parse(s: &str) -> Result<Pattern> {
+    match s {
+        "solid" => Ok(Pattern::Solid),
+        "striped" => Ok(Pattern::Striped),
+        "gradient" => Ok(Pattern::Gradient),
+        _ => Err(anyhow!(
+            "Unknown pattern {}, use solid, striped or gradient",
+            s
+        )),
+    }
+}
+
+/// Color of a segment, shaded by the pattern of the snake. Heads are
+/// never shaded.
+pub fn segment_color(segment: &Segment, pattern: Pattern) -> Color {
+    let factor = match (pattern, segment.part) {
+        (_, Part::Head) | (Pattern::Solid, _) => 1.0,
+        (Pattern::Striped, _) => match segment.number % 2 {
+            0 => 1.0,
+            _ => 0.6,
+        },
+        (Pattern::Gradient, _) => {
+            let step = segment.number % GRADIENT;
+            let distance = step.min(GRADIENT - step) as f64;
+            1.0 - 0.5 * distance / (GRADIENT / 2) as f64
+        }
+    };
+    let shade = |c: u8| (c as f64 * factor).round() as u8;
+    let color = segment.color;
+    Color::new(shade(color.r), shade(color.g), shade(color.b))
+}
+
+/// How many colors the terminal can show.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    #[default]
+    Ansi16,
+}
+
+impl FromStr for ColorDepth {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "truecolor" | "24bit" => Ok(ColorDepth::TrueColor),
+            "256" => Ok(ColorDepth::Ansi256),
+            "16" => Ok(ColorDepth::Ansi16),
+            _ => Err(anyhow!(
+                "Unknown color depth {}, use truecolor, 256 or 16",
+                s
+            )),
+        }
+    }
+}
+
+impl ColorDepth {
+    /// Guesses the color depth from `COLORTERM` and `TERM`.
+    pub fn detect() -> Self {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+
+    /// Escape sequence that sets the closest color we can show as the
+    /// foreground color.
+    pub fn fg(&self, color: Color) -> String {
+        match self {
+            ColorDepth::TrueColor => Fg(Rgb(color.r, color.g, color.b)).to_string(),
+            ColorDepth::Ansi256 => {
+                let level = |c: u8| {
+                    (0..CUBE.len())
+                        .min_by_key(|i| (CUBE[*i] as i16 - c as i16).abs())
+                        .unwrap() as u8
+                };
+                Fg(AnsiValue::rgb(
+                    level(color.r),
+                    level(color.g),
+                    level(color.b),
+                ))
+                .to_string()
+            }
+            ColorDepth::Ansi16 => {
+                let closest = (0..ANSI.len())
+                    .min_by(|a, b| {
+                        color
+                            .distance(&ANSI[*a])
+                            .total_cmp(&color.distance(&ANSI[*b]))
+                    })
+                    .unwrap();
+                Fg(AnsiValue(closest as u8 + 1)).to_string()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_parse_hex() {
+        assert_eq!(color_parse("ff8800").unwrap(), Color::new(255, 136, 0));
+        assert_eq!(color_parse("#FF8800").unwrap(), Color::new(255, 136, 0));
+        assert_eq!(color_parse("000000").unwrap(), Color::new(0, 0, 0));
+    }
+
+    #[test]
+    fn color_parse_invalid() {
+        assert!(color_parse("").is_err());
+        assert!(color_parse("fff").is_err());
+        assert!(color_parse("ff88001").is_err());
+        assert!(color_parse("gg8800").is_err());
+        assert!(color_parse("##ff88").is_err());
+        assert!(color_parse("ff88é").is_err());
+    }
+
+    #[test]
+    fn color_depth_parse() {
+        assert_eq!(
+            "truecolor".parse::<ColorDepth>().unwrap(),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(
+            "24bit".parse::<ColorDepth>().unwrap(),
+            ColorDepth::TrueColor
+        );
+        assert_eq!("256".parse::<ColorDepth>().unwrap(), ColorDepth::Ansi256);
+        assert_eq!("16".parse::<ColorDepth>().unwrap(), ColorDepth::Ansi16);
+        assert!("8".parse::<ColorDepth>().is_err());
+    }
+
+    #[test]
+    fn fg_truecolor() {
+        let fg = ColorDepth::TrueColor.fg(Color::new(255, 136, 0));
+        assert_eq!(fg, "\x1b[38;2;255;136;0m");
+    }
+
+    #[test]
+    fn fg_ansi256_uses_cube() {
+        // 16 + 36 * r + 6 * g + b with the levels of the cube
+        assert_eq!(
+            ColorDepth::Ansi256.fg(Color::new(255, 0, 0)),
+            "\x1b[38;5;196m"
+        );
+        assert_eq!(
+            ColorDepth::Ansi256.fg(Color::new(100, 100, 100)),
+            "\x1b[38;5;59m"
+        );
+        assert_eq!(ColorDepth::Ansi256.fg(Color::new(0, 0, 0)), "\x1b[38;5;16m");
+    }
+
+    #[test]
+    fn fg_ansi16_picks_closest() {
+        assert_eq!(
+            ColorDepth::Ansi16.fg(Color::new(200, 10, 10)),
+            "\x1b[38;5;1m"
+        );
+        assert_eq!(ColorDepth::Ansi16.fg(Color::new(250, 5, 5)), "\x1b[38;5;9m");
+        assert_eq!(
+            ColorDepth::Ansi16.fg(Color::new(250, 250, 250)),
+            "\x1b[38;5;15m"
+        );
+        assert_eq!(
+            ColorDepth::Ansi16.fg(Color::new(10, 10, 220)),
+            "\x1b[38;5;4m"
+        );
+    }
+
+    fn segment(part: Part, number: u32) -> Segment {
+        Segment {
+            id: 1,
+            color: Color::new(200, 100, 0),
+            part,
+            number,
+        }
+    }
+
+    #[test]
+    fn segment_color_patterns() {
+        let color = Color::new(200, 100, 0);
+        let dark = Color::new(120, 60, 0);
+        assert_eq!(
+            segment_color(&segment(Part::Body, 1), Pattern::Solid),
+            color
+        );
+        assert_eq!(
+            segment_color(&segment(Part::Body, 0), Pattern::Striped),
+            color
+        );
+        assert_eq!(
+            segment_color(&segment(Part::Body, 1), Pattern::Striped),
+            dark
+        );
+        assert_eq!(
+            segment_color(&segment(Part::Body, 0), Pattern::Gradient),
+            color
+        );
+        assert_eq!(
+            segment_color(&segment(Part::Body, GRADIENT / 2), Pattern::Gradient),
+            Color::new(100, 50, 0)
+        );
+        // heads are never shaded
+        assert_eq!(
+            segment_color(&segment(Part::Head, 1), Pattern::Striped),
+            color
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use crate::files;
+use crate::palette::ColorDepth;
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use termion::color::*;
+use unicode_width::UnicodeWidthStr;
+use xenopeltis_common::{Field, Part};
+
+const EMOJI: &str = include_str!("../themes/emoji.toml");
+const UNICODE: &str = include_str!("../themes/unicode.toml");
+const ASCII: &str = include_str!("../themes/ascii.toml");
+
+/// Terminal color, by name.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ThemeColor {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    LightBlack,
+    LightRed,
+    LightGreen,
+    LightYellow,
+    LightBlue,
+    LightMagenta,
+    LightCyan,
+    LightWhite,
+}
+
+impl ThemeColor {
+    /// Escape sequence that sets this as the foreground color.
+    pub fn fg(&self) -> String {
+        use ThemeColor::*;
+        match self {
+            Black => Fg(termion::color::Black).to_string(),
+            Red => Fg(termion::color::Red).to_string(),
+            Green => Fg(termion::color::Green).to_string(),
+            Yellow => Fg(termion::color::Yellow).to_string(),
+            Blue => Fg(termion::color::Blue).to_string(),
+            Magenta => Fg(termion::color::Magenta).to_string(),
+            Cyan => Fg(termion::color::Cyan).to_string(),
+            White => Fg(termion::color::White).to_string(),
+            LightBlack => Fg(termion::color::LightBlack).to_string(),
+            LightRed => Fg(termion::color::LightRed).to_string(),
+            LightGreen => Fg(termion::color::LightGreen).to_string(),
+            LightYellow => Fg(termion::color::LightYellow).to_string(),
+            LightBlue => Fg(termion::color::LightBlue).to_string(),
+            LightMagenta => Fg(termion::color::LightMagenta).to_string(),
+            LightCyan => Fg(termion::color::LightCyan).to_string(),
+            LightWhite => Fg(termion::color::LightWhite).to_string(),
+        }
+    }
+}
+
+/// How one kind of field is drawn.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Glyph {
+    /// Two columns wide: two narrow characters or a single wide one.
+    pub text: String,
+    pub color: Option<ThemeColor>,
+}
+
+/// Glyphs and colors for every kind of field.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Theme {
+    pub empty: Glyph,
+    pub food: Glyph,
+    pub rotten: Glyph,
+    /// Snakes are drawn in the color of their player.
+    pub snake: Glyph,
+    /// Heads and tails of snakes, drawn like the rest of the snake if not
+    /// set.
+    pub head: Option<Glyph>,
+    pub tail: Option<Glyph>,
+    pub wall: Glyph,
+    /// Show the minimap, which is drawn with braille characters.
+    #[serde(default = "minimap_default")]
+    pub minimap: bool,
+    /// Colors the terminal can show, not part of the theme file.
+    #[serde(skip)]
+    pub colors: ColorDepth,
+}
+
+fn minimap_default() -> bool {
+    true
+}
+
+impl Theme {
+    /// Loads a built-in theme by name, a user theme by name from the config
+    /// directory, or a theme file by path.
+    pub fn load(theme: &str) -> Result<Self> {
+        let builtin = [("emoji", EMOJI), ("unicode", UNICODE), ("ascii", ASCII)];
+        let data = files::load(theme, &builtin, "themes")
+            .with_context(|| format!("Loading theme {}", theme))?;
+
+        let theme: Theme =
+            toml::from_str(&data).with_context(|| format!("Parsing theme {}", theme))?;
+        theme.validate()?;
+        Ok(theme)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let glyphs = [
+            ("empty", Some(&self.empty)),
+            ("food", Some(&self.food)),
+            ("rotten", Some(&self.rotten)),
+            ("snake", Some(&self.snake)),
+            ("head", self.head.as_ref()),
+            ("tail", self.tail.as_ref()),
+            ("wall", Some(&self.wall)),
+        ];
+        for (name, glyph) in glyphs {
+            let glyph = match glyph {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+            if glyph.text.width() != 2 {
+                return Err(anyhow!("Glyph for {} must be two columns wide", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Glyph of a field.
+    pub fn glyph(&self, field: Field) -> &Glyph {
+        match field {
+            Field::Empty => &self.empty,
+            Field::Food(false) => &self.food,
+            Field::Food(true) => &self.rotten,
+            Field::Snake(segment) => match segment.part {
+                Part::Head => self.head.as_ref().unwrap_or(&self.snake),
+                Part::Body => &self.snake,
+                Part::Tail => self.tail.as_ref().unwrap_or(&self.snake),
+            },
+            Field::Wall => &self.wall,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xenopeltis_common::{Color, Segment};
+
+    /// Writes a theme file and loads it.
+    fn load_file(name: &str, data: &str) -> Result<Theme> {
+        let path = std::env::temp_dir().join(format!(
+            "xenopeltis-theme-{}-{}.toml",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, data).unwrap();
+        let theme = Theme::load(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        theme
+    }
+
+    /// Theme file with the given food glyph.
+    fn with_food(food: &str) -> String {
+        format!(
+            "empty = {{ text = \"  \" }}\n\
+             food = {{ text = \"{}\" }}\n\
+             rotten = {{ text = \"::\" }}\n\
+             snake = {{ text = \"[]\" }}\n\
+             wall = {{ text = \"##\" }}\n",
+            food
+        )
+    }
+
+    #[test]
+    fn load_builtin() {
+        for name in ["emoji", "unicode", "ascii"] {
+            Theme::load(name).unwrap();
+        }
+        assert!(Theme::load("no-such-theme").is_err());
+    }
+
+    #[test]
+    fn load_file_defaults() {
+        let theme = load_file("defaults", &with_food("()")).unwrap();
+        assert!(theme.minimap);
+        let head = Field::Snake(Segment {
+            id: 0,
+            color: Color { r: 0, g: 0, b: 0 },
+            part: Part::Head,
+            number: 0,
+        });
+        assert_eq!(theme.glyph(head).text, "[]");
+        assert_eq!(theme.glyph(Field::Food(false)).text, "()");
+    }
+
+    #[test]
+    fn validate_width() {
+        load_file("wide", &with_food("🍏")).unwrap();
+        let error = load_file("narrow", &with_food("o")).unwrap_err();
+        assert_eq!(error.to_string(), "Glyph for food must be two columns wide");
+        assert!(load_file("too-wide", &with_food("🍏🍏")).is_err());
+        assert!(load_file("empty", &with_food("")).is_err());
+    }
+
+    #[test]
+    fn unknown_field() {
+        let data = with_food("()") + "sky = { text = \"  \" }\n";
+        assert!(load_file("unknown", &data).is_err());
+    }
+}
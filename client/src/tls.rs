@@ -0,0 +1,157 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio_rustls::rustls::client::{ServerCertVerified, ServerCertVerifier};
+use tokio_rustls::rustls::{
+    Certificate, ClientConfig, Error, OwnedTrustAnchor, RootCertStore, ServerName,
+};
+use tokio_rustls::TlsConnector;
+
+/// Accepts any certificate, for servers with self-signed certificates.
+struct InsecureVerifier;
+
+impl ServerCertVerifier for InsecureVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+fn roots(ca: Option<&Path>) -> Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    match ca {
+        Some(path) => {
+            let mut reader = BufReader::new(
+                File::open(path).with_context(|| format!("Reading CA {}", path.display()))?,
+            );
+            let (added, _) = roots.add_parsable_certificates(&rustls_pemfile::certs(&mut reader)?);
+            if added == 0 {
+                return Err(anyhow!("No certificates in {}", path.display()));
+            }
+        }
+        None => {
+            roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+                OwnedTrustAnchor::from_subject_spki_name_constraints(
+                    ta.subject,
+                    ta.spki,
+                    ta.name_constraints,
+                )
+            }));
+        }
+    }
+    Ok(roots)
+}
+
+/// Builds the connector for TLS connections, trusting either the given CA
+/// or the usual web roots.
+pub fn connector(ca: Option<&Path>, insecure: bool) -> Result<TlsConnector> {
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots(ca)?)
+        .with_no_client_auth();
+    if insecure {
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(InsecureVerifier));
+    }
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// Name the server certificate has to be valid for, taken from the address.
+pub fn server_name(server: &str) -> Result<ServerName> {
+    let host = match server.rsplit_once(':') {
+        Some((host, _)) => host,
+        None => server,
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    ServerName::try_from(host).map_err(|_| anyhow!("Invalid server name: {}", host))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::rustls::{PrivateKey, ServerConfig};
+    use tokio_rustls::TlsAcceptor;
+
+    /// Serves a self-signed certificate for localhost to one client, which
+    /// gets a greeting if it trusts the certificate. Returns the address and
+    /// the path of the certificate.
+    async fn serve(name: &str) -> (String, std::path::PathBuf) {
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("xenopeltis-ca-{}-{}.pem", std::process::id(), name));
+        std::fs::write(&path, generated.serialize_pem().unwrap()).unwrap();
+
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![Certificate(generated.serialize_der().unwrap())],
+                PrivateKey(generated.serialize_private_key_der()),
+            )
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            if let Ok(mut stream) = acceptor.accept(stream).await {
+                let _ = stream.write_all(b"snake").await;
+                let _ = stream.flush().await;
+            }
+        });
+        (format!("localhost:{}", address.port()), path)
+    }
+
+    async fn greeting(server: &str, connector: TlsConnector) -> Result<[u8; 5]> {
+        let stream = TcpStream::connect(server).await?;
+        let mut stream = connector.connect(server_name(server)?, stream).await?;
+        let mut data = [0; 5];
+        stream.read_exact(&mut data).await?;
+        Ok(data)
+    }
+
+    #[tokio::test]
+    async fn connector_trusts_ca() {
+        let (server, ca) = serve("trusted").await;
+        let connector = connector(Some(&ca), false).unwrap();
+        std::fs::remove_file(&ca).unwrap();
+        assert_eq!(&greeting(&server, connector).await.unwrap(), b"snake");
+    }
+
+    #[tokio::test]
+    async fn connector_rejects_self_signed() {
+        let (server, ca) = serve("untrusted").await;
+        std::fs::remove_file(&ca).unwrap();
+        let connector = connector(None, false).unwrap();
+        assert!(greeting(&server, connector).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn connector_insecure() {
+        let (server, ca) = serve("insecure").await;
+        std::fs::remove_file(&ca).unwrap();
+        let connector = connector(None, true).unwrap();
+        assert_eq!(&greeting(&server, connector).await.unwrap(), b"snake");
+    }
+
+    #[test]
+    fn server_names() {
+        assert!(server_name("localhost:8000").is_ok());
+        assert!(server_name("example.com").is_ok());
+        assert!(server_name("[::1]:8000").is_ok());
+        assert!(server_name("not a name:8000").is_err());
+    }
+}
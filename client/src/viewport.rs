@@ -0,0 +1,125 @@
+use xenopeltis_common::Coordinate;
+
+/// Part of the board that fits in the terminal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Viewport {
+    /// Board row shown at the top.
+    pub row: usize,
+    /// Board column shown at the left.
+    pub col: usize,
+    pub rows: usize,
+    pub cols: usize,
+}
+
+/// Moves the start of a view so that the position stays at least a quarter
+/// of its length away from both ends, centering it otherwise.
+fn scroll(start: usize, length: usize, position: usize) -> usize {
+    let margin = length / 4;
+    if position < start + margin || position + margin >= start + length {
+        position.saturating_sub(length / 2)
+    } else {
+        start
+    }
+}
+
+impl Viewport {
+    /// Fits the viewport into the space of the terminal, in rows and
+    /// columns of fields, and scrolls it to follow the focus.
+    pub fn update(
+        &self,
+        (board_rows, board_cols): (usize, usize),
+        (space_rows, space_cols): (usize, usize),
+        focus: Option<Coordinate>,
+    ) -> Viewport {
+        let rows = board_rows.min(space_rows);
+        let cols = board_cols.min(space_cols);
+        let (mut row, mut col) = (self.row, self.col);
+        if let Some(focus) = focus {
+            row = scroll(row, rows, focus.row);
+            col = scroll(col, cols, focus.col);
+        }
+
+        Viewport {
+            row: row.min(board_rows - rows),
+            col: col.min(board_cols - cols),
+            rows,
+            cols,
+        }
+    }
+
+    pub fn contains(&self, coordinate: Coordinate) -> bool {
+        (self.row..self.row + self.rows).contains(&coordinate.row)
+            && (self.col..self.col + self.cols).contains(&coordinate.col)
+    }
+
+    /// Checks if the board is larger than the viewport.
+    pub fn scrolls(&self, (board_rows, board_cols): (usize, usize)) -> bool {
+        self.rows < board_rows || self.cols < board_cols
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(row: usize, col: usize, rows: usize, cols: usize) -> Viewport {
+        Viewport {
+            row,
+            col,
+            rows,
+            cols,
+        }
+    }
+
+    #[test]
+    fn board_fits() {
+        let focus = Some(Coordinate::new(9, 19));
+        let updated = Viewport::default().update((10, 20), (40, 100), focus);
+        assert_eq!(updated, view(0, 0, 10, 20));
+        assert!(!updated.scrolls((10, 20)));
+    }
+
+    #[test]
+    fn clamps_to_board_end() {
+        let focus = Some(Coordinate::new(99, 99));
+        let updated = Viewport::default().update((100, 100), (10, 10), focus);
+        assert_eq!(updated, view(90, 90, 10, 10));
+        assert!(updated.scrolls((100, 100)));
+    }
+
+    #[test]
+    fn clamps_to_board_start() {
+        let focus = Some(Coordinate::new(1, 2));
+        let updated = view(50, 50, 10, 10).update((100, 100), (10, 10), focus);
+        assert_eq!(updated, view(0, 0, 10, 10));
+    }
+
+    #[test]
+    fn scrolls_only_near_edges() {
+        // far enough from the edges, the view stays
+        let focus = Some(Coordinate::new(25, 25));
+        let updated = view(20, 20, 10, 10).update((100, 100), (10, 10), focus);
+        assert_eq!(updated, view(20, 20, 10, 10));
+
+        // within a quarter of the edge, the focus is centered
+        let focus = Some(Coordinate::new(21, 28));
+        let updated = view(20, 20, 10, 10).update((100, 100), (10, 10), focus);
+        assert_eq!(updated, view(16, 23, 10, 10));
+    }
+
+    #[test]
+    fn clamps_without_focus() {
+        // the terminal grew, so the view can't start as far down anymore
+        let updated = view(50, 50, 10, 10).update((60, 60), (20, 20), None);
+        assert_eq!(updated, view(40, 40, 20, 20));
+    }
+
+    #[test]
+    fn contains() {
+        let view = view(10, 20, 5, 5);
+        assert!(view.contains(Coordinate::new(10, 20)));
+        assert!(view.contains(Coordinate::new(14, 24)));
+        assert!(!view.contains(Coordinate::new(15, 20)));
+        assert!(!view.contains(Coordinate::new(10, 19)));
+    }
+}
@@ -1,29 +1,120 @@
-use rand::distributions::{Distribution, Standard};
-use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
 
 /// Messages coming from the client to the server.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum ClientMessage {
+    Hello(HelloMessage),
     Direction(DirectionMessage),
     Restart,
     Quit,
+    /// Vote to pause the game, or take back the vote.
+    Pause,
+    Leaderboard(LeaderboardPeriod),
+    Admin(AdminMessage),
+    /// Create an account and log in to it.
+    Register(CredentialsMessage),
+    Login(CredentialsMessage),
+    /// Log in with a pre-shared token, meant for bots.
+    Token(TokenMessage),
+    /// Sent first by a proxy, with the address of the client it connects
+    /// for. Only trusted proxies may send it.
+    Forwarded(ForwardedMessage),
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// Address of the client a proxy connects for.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ForwardedMessage {
+    pub address: SocketAddr,
+}
+
+/// Client introduces itself after connecting.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HelloMessage {
+    pub name: String,
+    pub skin: Skin,
+}
+
+/// How a player wants its snake to look.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Skin {
+    /// Color of the snake, the server picks one if not set.
+    pub color: Option<Color>,
+    /// Drawn instead of the usual head, one or two characters.
+    pub head: Option<String>,
+    pub pattern: Pattern,
+}
+
+/// Pattern of the body of a snake.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Pattern {
+    #[default]
+    Solid,
+    /// Every other segment is darker.
+    Striped,
+    /// Segments get darker and lighter again along the body.
+    Gradient,
+}
+
+/// Darkest channel value the brightest channel of a skin color may have,
+/// so snakes stay visible on dark terminals.
+pub const SKIN_BRIGHTNESS_MIN: u8 = 96;
+
+impl Skin {
+    /// Checks if a skin is acceptable.
+    pub fn valid(&self) -> bool {
+        let color = self
+            .color
+            .map(|color| color.r.max(color.g).max(color.b) >= SKIN_BRIGHTNESS_MIN)
+            .unwrap_or(true);
+        let head = self
+            .head
+            .as_ref()
+            .map(|head| {
+                (1..=2).contains(&head.chars().count())
+                    && head.chars().all(|c| !c.is_control() && !c.is_whitespace())
+            })
+            .unwrap_or(true);
+        color && head
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CredentialsMessage {
+    pub name: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TokenMessage {
+    pub token: String,
+}
+
+/// Shortest allowed account password.
+pub const PASSWORD_LENGTH_MIN: usize = 8;
+
+/// Longest allowed player name.
+pub const NAME_LENGTH_MAX: usize = 16;
+
+/// Checks if a player name is acceptable.
+pub fn name_valid(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().count() <= NAME_LENGTH_MAX
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Direction {
     Up,
     Down,
     Left,
+    #[default]
     Right,
 }
 
-impl Default for Direction {
-    fn default() -> Self {
-        Direction::Right
-    }
-}
-
 impl Direction {
     pub fn offset(&self) -> (isize, isize) {
         match self {
@@ -51,51 +142,374 @@ pub struct DirectionMessage {
     pub direction: Direction,
 }
 
+/// Client wants to run an admin command, needs the admin password.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AdminMessage {
+    pub password: String,
+    pub command: AdminCommand,
+}
+
+/// Commands for managing a running server.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum AdminCommand {
+    /// Changes the game itself.
+    Game(GameCommand),
+    /// Changes the server configuration.
+    Config(ConfigCommand),
+}
+
+/// Admin commands the game handles.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum GameCommand {
+    /// List connected players and bots.
+    Players,
+    /// Disconnect a player.
+    Kick(PlayerId),
+    /// Disconnect and refuse all players from an address.
+    Ban(IpAddr),
+    /// Lift a ban.
+    Unban(IpAddr),
+    /// Disconnect and refuse all players with a name.
+    BanName(String),
+    /// Lift a ban on a name.
+    UnbanName(String),
+    Pause,
+    Resume,
+    /// Remove all snakes and food and start over.
+    Reset,
+    /// Show a message to all players.
+    Broadcast(String),
+}
+
+/// Admin commands that change the configuration, the game picks them up
+/// from there.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum ConfigCommand {
+    /// Set milliseconds between game ticks.
+    Tick(u64),
+    /// Set amount of food on the board.
+    Food(usize),
+}
+
+/// Result of running an admin command.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum AdminResponse {
+    Done,
+    Players(Vec<PlayerInfo>),
+    Error(String),
+}
+
+/// Player as seen by admins.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PlayerInfo {
+    pub id: PlayerId,
+    /// Name the player introduced itself with.
+    pub name: Option<String>,
+    /// Address the player connected from, bots have none.
+    pub address: Option<SocketAddr>,
+    /// Length of the snake, if currently playing.
+    pub length: Option<usize>,
+}
+
+/// Identifies a player within a game.
+pub type PlayerId = u64;
+
 /// Messages coming from the server to the client.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum ServerMessage {
     FieldChange(FieldChangeMessage),
     PlayerState(PlayerStateMessage),
+    Motd(MotdMessage),
+    Broadcast(BroadcastMessage),
+    Pause(PauseMessage),
+    Leaderboard(LeaderboardMessage),
+    Admin(AdminResponse),
+    Auth(AuthMessage),
+    /// Server is about to close the connection.
+    Disconnect(DisconnectMessage),
+    /// Server could not handle a message of the client.
+    Error(ErrorMessage),
+    /// Server is going to shut down soon.
+    Shutdown(ShutdownMessage),
+    /// A snake died, sent to everyone.
+    PlayerDied(PlayerDiedMessage),
+    /// Tells a client which player it is and how large the board is, sent
+    /// first.
+    Welcome(WelcomeMessage),
+    /// Positions of all snake heads, sent after every tick.
+    Heads(HeadsMessage),
+    /// A player joined or changed its skin, sent to everyone.
+    PlayerJoined(PlayerJoinedMessage),
+    /// Scores of all snakes, sent after every tick.
+    Scores(ScoresMessage),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ScoresMessage {
+    pub scores: Vec<(PlayerId, usize)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PlayerJoinedMessage {
+    pub id: PlayerId,
+    pub name: Option<String>,
+    pub skin: Skin,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WelcomeMessage {
+    pub id: PlayerId,
+    /// Size of the board, in rows and columns.
+    pub size: (usize, usize),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HeadsMessage {
+    pub heads: Vec<(PlayerId, Coordinate)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PlayerDiedMessage {
+    pub id: PlayerId,
+    /// Name of the player, not set for bots.
+    pub name: Option<String>,
+    /// Player whose snake was hit, if it wasn't the snake itself.
+    pub killer: Option<PlayerId>,
+    pub killer_name: Option<String>,
+    pub cause: DeathCause,
+    /// Final score of the player, including points for kills.
+    pub score: usize,
+}
+
+/// What a snake ran into.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeathCause {
+    Wall,
+    /// Another snake, or what is left of a dead one.
+    Snake,
+    /// Its own body.
+    Itself,
+    /// The edge of a board without walls.
+    Edge,
+}
+
+impl fmt::Display for DeathCause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use DeathCause::*;
+        let text = match self {
+            Wall => "Hit a wall",
+            Snake => "Hit a snake",
+            Itself => "Bit itself",
+            Edge => "Left the board",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ShutdownMessage {
+    /// Seconds until the server disconnects everyone.
+    pub seconds: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DisconnectMessage {
+    pub reason: DisconnectReason,
+}
+
+/// Why the server closes a connection.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisconnectReason {
+    Kicked,
+    Banned,
+    ServerFull,
+    TooManyConnections,
+    TooManyMessages,
+    MessageTooLarge,
+    InvalidMessage,
+    InvalidName,
+    Shutdown,
+    NotAuthorized,
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use DisconnectReason::*;
+        let text = match self {
+            Kicked => "Kicked by admin",
+            Banned => "Banned",
+            ServerFull => "Server is full",
+            TooManyConnections => "Too many connections from your address",
+            TooManyMessages => "Too many messages",
+            MessageTooLarge => "Message too large",
+            InvalidMessage => "Invalid message",
+            InvalidName => "Invalid name",
+            Shutdown => "Server is shutting down",
+            NotAuthorized => "Wrong admin password or token",
+        };
+        f.write_str(text)
+    }
+}
+
+impl std::error::Error for DisconnectReason {}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ErrorMessage {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// Message could not be decoded.
+    InvalidMessage,
+    /// Player needs to log in first.
+    NotAuthenticated,
+    /// Feature is disabled or failed on the server.
+    Unavailable,
+    /// Skin was rejected, the default one is used instead.
+    InvalidSkin,
+}
+
+/// Outcome of authentication, or a request to authenticate.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum AuthMessage {
+    /// Server only lets players join after logging in.
+    Required,
+    LoggedIn(String),
+    Failed(String),
+}
+
+/// Message of the day, sent when connecting.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MotdMessage {
+    pub text: String,
+}
+
+/// Game was paused or resumed.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PauseMessage {
+    /// Why the game is paused, none if it is running.
+    pub reason: Option<PauseReason>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PauseReason {
+    /// Paused by the server admin.
+    Admin,
+    /// Most players voted to pause.
+    Vote,
+    /// Nobody is connected.
+    Empty,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LeaderboardPeriod {
+    AllTime,
+    Weekly,
+}
+
+/// Best players, sent when requested.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LeaderboardMessage {
+    pub period: LeaderboardPeriod,
+    pub entries: Vec<LeaderboardEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LeaderboardEntry {
+    pub name: String,
+    pub best_length: usize,
+    pub kills: usize,
+    pub wins: usize,
+    pub games: usize,
+}
+
+/// Message from the server admin to all players.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BroadcastMessage {
+    pub text: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct PlayerStateMessage {
     pub state: PlayerState,
+    /// How the player died, when it lost by dying.
+    pub death: Option<PlayerDiedMessage>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub enum PlayerState {
+    #[default]
     Playing,
     Won,
     Lost,
+    /// Waiting for room on the board to spawn.
+    Waiting,
 }
 
 /// RGB color.
-#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
-pub enum Color {
-    Blue,
-    Cyan,
-    Green,
-    Magenta,
-    Red,
-    Yellow,
-}
-
-impl Distribution<Color> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Color {
-        use Color::*;
-        match rng.gen_range(0..6) {
-            0 => Blue,
-            1 => Cyan,
-            2 => Green,
-            3 => Magenta,
-            4 => Red,
-            5 => Yellow,
-            _ => unreachable!(),
-        }
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const fn new(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b }
+    }
+
+    /// Color from a hue in degrees, saturation and value between 0 and 1.
+    pub fn hsv(hue: f64, saturation: f64, value: f64) -> Color {
+        let chroma = value * saturation;
+        let sector = hue.rem_euclid(360.0) / 60.0;
+        let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+        let (r, g, b) = match sector as usize {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let m = value - chroma;
+        let channel = |c: f64| ((c + m) * 255.0).round() as u8;
+        Color::new(channel(r), channel(g), channel(b))
+    }
+
+    /// How different two colors look, using the weighted euclidean distance
+    /// known as redmean.
+    pub fn distance(&self, other: &Color) -> f64 {
+        let mean = (self.r as f64 + other.r as f64) / 2.0;
+        let r = self.r as f64 - other.r as f64;
+        let g = self.g as f64 - other.g as f64;
+        let b = self.b as f64 - other.b as f64;
+        ((2.0 + mean / 256.0) * r * r + 4.0 * g * g + (2.0 + (255.0 - mean) / 256.0) * b * b).sqrt()
     }
 }
 
+/// Which part of a snake a segment is.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Part {
+    Head,
+    Body,
+    Tail,
+}
+
+/// Segment of a snake.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Segment {
+    /// Player the snake belongs to.
+    pub id: PlayerId,
+    pub color: Color,
+    pub part: Part,
+    /// Counts up from the tail the snake spawned with, so that a segment
+    /// keeps its number while the snake moves.
+    pub number: u32,
+}
+
 /// What is in a field?
 ///
 /// Can be empty, apple (edible) or snake. Snakes are differentiated
@@ -105,15 +519,12 @@ pub enum Field {
     Empty,
     Wall,
     Food(bool),
-    Snake(Color),
+    Snake(Segment),
 }
 
 impl Field {
     pub fn food(&self) -> bool {
-        match self {
-            Field::Food(_) => true,
-            _ => false,
-        }
+        matches!(self, Field::Food(_))
     }
 }
 
@@ -136,3 +547,40 @@ pub struct FieldChangeMessage {
     pub coordinate: Coordinate,
     pub field: Field,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn skin(color: Option<Color>, head: Option<&str>) -> Skin {
+        Skin {
+            color,
+            head: head.map(String::from),
+            pattern: Pattern::Solid,
+        }
+    }
+
+    #[test]
+    fn skin_default_is_valid() {
+        assert!(Skin::default().valid());
+    }
+
+    #[test]
+    fn skin_color_brightness() {
+        assert!(skin(Some(Color::new(0, 0, SKIN_BRIGHTNESS_MIN)), None).valid());
+        assert!(skin(Some(Color::new(255, 0, 0)), None).valid());
+        assert!(!skin(Some(Color::new(95, 95, 95)), None).valid());
+        assert!(!skin(Some(Color::new(0, 0, 0)), None).valid());
+    }
+
+    #[test]
+    fn skin_head() {
+        assert!(skin(None, Some("@")).valid());
+        assert!(skin(None, Some("<>")).valid());
+        assert!(skin(None, Some("ö")).valid());
+        assert!(!skin(None, Some("")).valid());
+        assert!(!skin(None, Some("abc")).valid());
+        assert!(!skin(None, Some(" ")).valid());
+        assert!(!skin(None, Some("\x1b[")).valid());
+    }
+}
@@ -0,0 +1,1715 @@
+use crate::map::{Map, SpawnPoint};
+use crate::mode::GameMode;
+use crate::palette;
+use crate::settings::{Corpse, CorpseKind, Spawn};
+use anyhow::{anyhow, Result};
+use log::*;
+use rand::seq::SliceRandom;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+use std::net::{IpAddr, SocketAddr};
+use tokio::sync::broadcast::{channel, Receiver, Sender};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use xenopeltis_common::*;
+
+const CHANNEL_SIZE: usize = 1024;
+
+/// The server hosts a single game, which is shown as a room with this id.
+pub const ROOM: &str = "default";
+
+/// Ticks to wait before searching the board for room again, after a snake
+/// didn't fit.
+const SPAWN_RETRY_TICKS: u64 = 10;
+
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+];
+
+/// Outcome of a single game of a named player.
+#[derive(Clone, Debug)]
+pub struct GameResult {
+    pub name: String,
+    pub length: usize,
+    pub kills: usize,
+    pub won: bool,
+}
+
+/// Numbers describing the game, for metrics and status.
+#[derive(Clone, Debug)]
+pub struct Stats {
+    pub players: usize,
+    pub snakes: usize,
+    pub food: usize,
+    pub longest: usize,
+    pub paused: bool,
+}
+
+/// Public information about a player, for the status API.
+#[derive(Clone, Debug, Serialize)]
+pub struct PlayerStats {
+    pub id: PlayerId,
+    pub name: Option<String>,
+    pub bot: bool,
+    /// Length of the snake, if the player is alive.
+    pub length: Option<usize>,
+    /// Score by the rules of the game mode, zero if the player is not alive.
+    pub score: usize,
+    pub kills: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Player {
+    snake: VecDeque<(usize, usize)>,
+    color: Color,
+    /// Number of the tail segment, counting up as the snake moves.
+    tail: u32,
+    direction: Direction,
+    growth: usize,
+    kills: usize,
+    bot: bool,
+    /// Ticks left in which the snake can't die, after spawning.
+    protection: u64,
+}
+
+impl Player {
+    /// Length of this player's snake.
+    pub fn length(&self) -> usize {
+        self.snake.len()
+    }
+
+    /// Snakes that died running into this player's snake.
+    pub fn kills(&self) -> usize {
+        self.kills
+    }
+}
+
+/// Remains of a dead snake that block the way until they turn into food.
+#[derive(Clone, Debug)]
+struct Remains {
+    /// Positions of the segments, with the field they left behind.
+    fields: Vec<((usize, usize), Field)>,
+    /// Ticks left until the remains turn into food.
+    ticks: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Client {
+    sender: UnboundedSender<ServerMessage>,
+    address: SocketAddr,
+    name: Option<String>,
+    /// Account the client logged in to.
+    account: Option<String>,
+    skin: Skin,
+}
+
+#[derive(Debug)]
+pub struct Game {
+    map: Map,
+    state: Vec<Vec<Field>>,
+    players: BTreeMap<PlayerId, Player>,
+    clients: BTreeMap<PlayerId, Client>,
+    clients_max: usize,
+    clients_per_address: usize,
+    bans: BTreeSet<IpAddr>,
+    bans_name: BTreeSet<String>,
+    events: Sender<ServerMessage>,
+    mode: Box<dyn GameMode>,
+    ticks: u64,
+    paused: bool,
+    pause_votes: BTreeSet<PlayerId>,
+    pause: Option<PauseReason>,
+    next_id: PlayerId,
+    motd: Option<String>,
+    food_current: usize,
+    food_target: usize,
+    bots_target: usize,
+    results: Option<UnboundedSender<GameResult>>,
+    auth_required: bool,
+    /// Players waiting for room on the board.
+    spawn_queue: VecDeque<PlayerId>,
+    /// Tick from which queued players and bots are spawned again.
+    spawn_retry: u64,
+    spawn: Spawn,
+    corpse: Corpse,
+    remains: Vec<Remains>,
+}
+
+impl Game {
+    pub fn new(map: Map, mode: Box<dyn GameMode>) -> Self {
+        let (events, _) = channel(CHANNEL_SIZE);
+
+        Game {
+            state: map.fields().clone(),
+            map,
+            players: BTreeMap::new(),
+            clients: BTreeMap::new(),
+            clients_max: usize::MAX,
+            clients_per_address: usize::MAX,
+            bans: BTreeSet::new(),
+            bans_name: BTreeSet::new(),
+            events,
+            mode,
+            ticks: 0,
+            paused: false,
+            pause_votes: BTreeSet::new(),
+            pause: Some(PauseReason::Empty),
+            next_id: 0,
+            motd: None,
+            food_current: 0,
+            food_target: 0,
+            bots_target: 0,
+            results: None,
+            auth_required: false,
+            spawn_queue: VecDeque::new(),
+            spawn_retry: 0,
+            spawn: Spawn::default(),
+            corpse: Corpse::default(),
+            remains: vec![],
+        }
+    }
+
+    fn id_next(&mut self) -> PlayerId {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// Registers a client connection. Returns the id of the client, the
+    /// broadcast events and the messages addressed only to this client.
+    pub fn client_add(
+        &mut self,
+        address: SocketAddr,
+    ) -> Result<
+        (
+            PlayerId,
+            Receiver<ServerMessage>,
+            UnboundedReceiver<ServerMessage>,
+        ),
+        DisconnectReason,
+    > {
+        if self.bans.contains(&address.ip()) {
+            return Err(DisconnectReason::Banned);
+        }
+
+        if self.clients.len() >= self.clients_max {
+            return Err(DisconnectReason::ServerFull);
+        }
+
+        let connections = self
+            .clients
+            .values()
+            .filter(|client| client.address.ip() == address.ip())
+            .count();
+        if connections >= self.clients_per_address {
+            return Err(DisconnectReason::TooManyConnections);
+        }
+
+        let id = self.id_next();
+        let (sender, receiver) = unbounded_channel();
+        self.clients.insert(
+            id,
+            Client {
+                sender,
+                address,
+                name: None,
+                account: None,
+                skin: Skin::default(),
+            },
+        );
+        let events = self.events.subscribe();
+        self.pause_update();
+        Ok((id, events, receiver))
+    }
+
+    /// Sets the name of a client.
+    pub fn client_name_set(&mut self, id: PlayerId, name: String) -> Result<(), DisconnectReason> {
+        if !name_valid(&name) {
+            return Err(DisconnectReason::InvalidName);
+        }
+
+        if self.bans_name.contains(&name) {
+            return Err(DisconnectReason::Banned);
+        }
+
+        if let Some(client) = self.clients.get_mut(&id) {
+            // logged in clients keep the name of their account
+            if client.account.is_none() {
+                client.name = Some(name);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Attaches an account to a client, which then plays under the account
+    /// name. Lets the player join if it was waiting for authentication.
+    pub fn client_login(&mut self, id: PlayerId, account: String) -> Result<()> {
+        if self
+            .clients
+            .get(&id)
+            .and_then(|client| client.account.as_ref())
+            .is_some()
+        {
+            return Err(anyhow!("Already logged in"));
+        }
+
+        if self
+            .clients
+            .values()
+            .any(|client| client.account.as_ref() == Some(&account))
+        {
+            return Err(anyhow!("Account is already playing"));
+        }
+
+        self.client_name_set(id, account.clone())?;
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.account = Some(account);
+        }
+
+        if !self.player_exists(id) {
+            self.player_add(id);
+        }
+
+        Ok(())
+    }
+
+    /// Checks if a client may play, which needs an account if authentication
+    /// is required.
+    pub fn client_joinable(&self, id: PlayerId) -> bool {
+        !self.auth_required
+            || self
+                .clients
+                .get(&id)
+                .and_then(|client| client.account.as_ref())
+                .is_some()
+    }
+
+    pub fn auth_required_set(&mut self, required: bool) {
+        self.auth_required = required;
+    }
+
+    /// Removes a client, which also closes its connection.
+    pub fn client_remove(&mut self, id: PlayerId) -> bool {
+        self.player_finish(id, false);
+        self.pause_votes.remove(&id);
+        self.spawn_queue.retain(|queued| *queued != id);
+        let removed = self.clients.remove(&id).is_some();
+        self.pause_update();
+        removed
+    }
+
+    /// Tells a client why it is being removed, then removes it.
+    pub fn client_disconnect(&mut self, id: PlayerId, reason: DisconnectReason) -> bool {
+        self.notify(id, ServerMessage::Disconnect(DisconnectMessage { reason }));
+        self.client_remove(id)
+    }
+
+    /// Tells a client that one of its messages could not be handled.
+    pub fn error(&self, id: PlayerId, code: ErrorCode, message: &str) {
+        self.notify(
+            id,
+            ServerMessage::Error(ErrorMessage {
+                code,
+                message: message.into(),
+            }),
+        );
+    }
+
+    /// Warns all clients that the server shuts down soon.
+    pub fn shutdown_announce(&self, seconds: u64) {
+        let _ = self
+            .events
+            .send(ServerMessage::Shutdown(ShutdownMessage { seconds }));
+    }
+
+    /// Disconnects all clients and stops recording results, which lets the
+    /// leaderboard finish writing.
+    pub fn shutdown(&mut self) {
+        let ids: Vec<_> = self.clients.keys().copied().collect();
+        for id in ids {
+            self.client_disconnect(id, DisconnectReason::Shutdown);
+        }
+        self.results = None;
+    }
+
+    /// Bans an address and disconnects all clients from it.
+    pub fn ban(&mut self, address: IpAddr) {
+        self.bans.insert(address);
+        let banned: Vec<_> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| client.address.ip() == address)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in banned {
+            info!("Kicking banned player {}", id);
+            self.client_disconnect(id, DisconnectReason::Banned);
+        }
+    }
+
+    pub fn unban(&mut self, address: IpAddr) -> bool {
+        self.bans.remove(&address)
+    }
+
+    /// Bans a name and disconnects all clients using it.
+    pub fn ban_name(&mut self, name: String) {
+        let banned: Vec<_> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| client.name.as_ref() == Some(&name))
+            .map(|(id, _)| *id)
+            .collect();
+        self.bans_name.insert(name);
+        for id in banned {
+            info!("Kicking banned player {}", id);
+            self.client_disconnect(id, DisconnectReason::Banned);
+        }
+    }
+
+    pub fn unban_name(&mut self, name: &str) -> bool {
+        self.bans_name.remove(name)
+    }
+
+    /// Sends the results of finished games here.
+    pub fn results_set(&mut self, results: UnboundedSender<GameResult>) {
+        self.results = Some(results);
+    }
+
+    pub fn clients_max_set(&mut self, max: usize) {
+        self.clients_max = max;
+    }
+
+    /// Sets the length of new snakes, how many free fields they want ahead
+    /// of them and for how many ticks they can't die.
+    pub fn spawn_set(&mut self, spawn: Spawn) {
+        self.spawn = spawn;
+    }
+
+    /// Sets what dead snakes leave behind.
+    pub fn corpse_set(&mut self, corpse: Corpse) {
+        self.corpse = corpse;
+    }
+
+    pub fn clients_per_address_set(&mut self, max: usize) {
+        self.clients_per_address = max;
+    }
+
+    /// Sends a message to a single client.
+    pub fn notify(&self, id: PlayerId, message: ServerMessage) {
+        if let Some(client) = self.clients.get(&id) {
+            let _ = client.sender.send(message);
+        }
+    }
+
+    /// Sends a message to all clients.
+    pub fn broadcast(&self, text: String) {
+        let _ = self
+            .events
+            .send(ServerMessage::Broadcast(BroadcastMessage { text }));
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            players: self.clients.len(),
+            snakes: self.players.len(),
+            food: self.food_current,
+            longest: self
+                .players
+                .values()
+                .map(|player| player.length())
+                .max()
+                .unwrap_or(0),
+            paused: self.paused(),
+        }
+    }
+
+    /// Connected clients and bots, without their addresses.
+    pub fn player_stats(&self) -> Vec<PlayerStats> {
+        let ids: BTreeSet<_> = self.clients.keys().chain(self.players.keys()).collect();
+        ids.into_iter()
+            .map(|id| {
+                let player = self.players.get(id);
+                PlayerStats {
+                    id: *id,
+                    name: self.clients.get(id).and_then(|client| client.name.clone()),
+                    bot: player.map(|player| player.bot).unwrap_or(false),
+                    length: player.map(|player| player.length()),
+                    score: player.map(|player| self.mode.score(player)).unwrap_or(0),
+                    kills: player.map(|player| player.kills).unwrap_or(0),
+                }
+            })
+            .collect()
+    }
+
+    pub fn mode_name(&self) -> &'static str {
+        self.mode.name()
+    }
+
+    pub fn pause_reason(&self) -> Option<PauseReason> {
+        self.pause
+    }
+
+    /// Current fields of the board, by row and column.
+    pub fn board(&self) -> &Vec<Vec<Field>> {
+        &self.state
+    }
+
+    /// Address a client connected from, bots have none.
+    pub fn client_address(&self, id: PlayerId) -> Option<SocketAddr> {
+        self.clients.get(&id).map(|client| client.address)
+    }
+
+    fn notify_state(&self, id: PlayerId, state: PlayerState) {
+        self.notify(
+            id,
+            ServerMessage::PlayerState(PlayerStateMessage { state, death: None }),
+        );
+    }
+
+    /// Lets a player join, or queues it until there is room on the board.
+    pub fn player_add(&mut self, id: PlayerId) {
+        if self.player_spawn(id, false) {
+            self.notify_state(id, PlayerState::Playing);
+        } else if !self.spawn_queue.contains(&id) {
+            info!("No room for player {}, queueing", id);
+            self.spawn_queue.push_back(id);
+            self.notify_state(id, PlayerState::Waiting);
+        }
+    }
+
+    /// Spawns queued players in order, as long as there is room.
+    fn spawn_queued(&mut self) {
+        while let Some(id) = self.spawn_queue.front().copied() {
+            if !self.player_spawn(id, false) {
+                break;
+            }
+            self.spawn_queue.pop_front();
+            self.notify_state(id, PlayerState::Playing);
+        }
+    }
+
+    /// Puts a new snake on the board, returns false if there is no room.
+    fn player_spawn(&mut self, id: PlayerId, bot: bool) -> bool {
+        let ((row, col), direction) = match self.spawn_position() {
+            Some(spawn) => spawn,
+            None => {
+                self.spawn_retry = self.ticks + SPAWN_RETRY_TICKS;
+                return false;
+            }
+        };
+        let color = match self.clients.get(&id).and_then(|client| client.skin.color) {
+            Some(color) => color,
+            None => {
+                let used: Vec<Color> = self.players.values().map(|player| player.color).collect();
+                palette::distinct(&used)
+            }
+        };
+        let mut snake = VecDeque::new();
+        let mut position = (row, col);
+        snake.push_back(position);
+        for _ in 1..self.spawn.length {
+            // spawn_position made sure the fields behind the head are free
+            position = self.step(position, direction.opposite()).unwrap();
+            snake.push_front(position);
+        }
+        info!(
+            "Adding player {} to ({}, {}) facing {:?} with color {:?}",
+            id, row, col, direction, color
+        );
+
+        let length = snake.len();
+        self.players.insert(
+            id,
+            Player {
+                snake,
+                color,
+                tail: 0,
+                direction,
+                growth: 0,
+                kills: 0,
+                bot,
+                protection: self.spawn.protection,
+            },
+        );
+        self.snake_draw(id, 0..length);
+        // the snake may have spawned on food
+        self.food_fill();
+
+        self.mode.on_join(id);
+        true
+    }
+
+    /// Picks a free position for a new snake, with room for its body behind
+    /// it. The snake faces away from the nearest obstacle, unless the spawn
+    /// point says otherwise, and positions with the longest clear runway
+    /// ahead, up to the configured one, are preferred. Only uses the spawn
+    /// points of the map if it has any, and avoids fields next to other
+    /// snakes. Snakes may spawn on food.
+    fn spawn_position(&self) -> Option<((usize, usize), Direction)> {
+        let spawns: Vec<_> = match self.map.spawns() {
+            [] => (0..self.state.len())
+                .flat_map(|row| (0..self.state[row].len()).map(move |col| (row, col)))
+                .map(|position| SpawnPoint {
+                    position,
+                    direction: None,
+                })
+                .collect(),
+            spawns => spawns.to_vec(),
+        };
+
+        let mut rng = rand::thread_rng();
+        let body = self.spawn.length - 1;
+        let room = self.state.len().max(self.state[0].len());
+        let mut best = 0;
+        let mut candidates = vec![];
+        for SpawnPoint {
+            position,
+            direction,
+        } in spawns
+        {
+            if !matches!(
+                self.state[position.0][position.1],
+                Field::Empty | Field::Food(_)
+            ) || DIRECTIONS
+                .iter()
+                .any(|dir| matches!(self.neighbour(position, *dir), Some(Field::Snake(_))))
+            {
+                continue;
+            }
+
+            let mut directions = match direction {
+                Some(direction) => vec![direction],
+                None => DIRECTIONS.to_vec(),
+            };
+            directions.shuffle(&mut rng);
+            let facing = directions
+                .into_iter()
+                .filter(|dir| self.runway(position, dir.opposite(), body) == body)
+                .map(|dir| (self.runway(position, dir, room), dir))
+                .max_by_key(|(ahead, _)| *ahead);
+
+            let (ahead, dir) = match facing {
+                Some(facing) => facing,
+                None => continue,
+            };
+            let runway = ahead.min(self.spawn.runway);
+            if runway > best {
+                best = runway;
+                candidates.clear();
+            }
+            if runway == best && runway > 0 {
+                candidates.push((position, dir));
+            }
+        }
+
+        candidates.choose(&mut rng).copied()
+    }
+
+    /// Free fields ahead of a position, up to the given limit.
+    fn runway(&self, mut position: (usize, usize), dir: Direction, limit: usize) -> usize {
+        for length in 0..limit {
+            position = match self.step(position, dir) {
+                Some(next) => next,
+                None => return length,
+            };
+            if !matches!(
+                self.state[position.0][position.1],
+                Field::Empty | Field::Food(_)
+            ) {
+                return length;
+            }
+        }
+
+        limit
+    }
+
+    pub fn player_exists(&mut self, id: PlayerId) -> bool {
+        self.players.contains_key(&id)
+    }
+
+    /// Removes a player whose game is over, recording the result if the
+    /// player is logged in. Anyone could pick the name of someone else.
+    fn player_finish(&mut self, id: PlayerId, won: bool) {
+        let name = self
+            .clients
+            .get(&id)
+            .and_then(|client| client.account.clone());
+        if let (Some(player), Some(name), Some(results)) =
+            (self.players.get(&id), name, &self.results)
+        {
+            let _ = results.send(GameResult {
+                name,
+                length: player.length(),
+                kills: player.kills,
+                won,
+            });
+        }
+
+        self.player_remove(id);
+    }
+
+    /// Player whose snake is at this position.
+    fn snake_at(&self, position: (usize, usize)) -> Option<PlayerId> {
+        self.players
+            .iter()
+            .find(|(_, player)| player.snake.contains(&position))
+            .map(|(id, _)| *id)
+    }
+
+    pub fn player_remove(&mut self, id: PlayerId) {
+        if let Some(player) = self.players.remove(&id) {
+            self.corpse_add(player.snake.into_iter().collect());
+        }
+    }
+
+    /// Turns the segments of a dead snake into food or remains, depending
+    /// on the corpse settings.
+    fn corpse_add(&mut self, segments: Vec<(usize, usize)>) {
+        let food: Vec<bool> = match self.corpse.kind {
+            CorpseKind::Decay if self.corpse.ticks > 0 => {
+                // the segments stay on the board as they are
+                let fields = segments
+                    .into_iter()
+                    .map(|(row, col)| ((row, col), self.state[row][col]))
+                    .collect();
+                self.remains.push(Remains {
+                    fields,
+                    ticks: self.corpse.ticks,
+                });
+                return;
+            }
+            CorpseKind::Food | CorpseKind::Decay => vec![true; segments.len()],
+            CorpseKind::Alternate => (0..segments.len()).map(|i| i % 2 == 0).collect(),
+            CorpseKind::Percent => {
+                let amount = segments.len() * self.corpse.percent as usize / 100;
+                let mut food = vec![false; segments.len()];
+                for i in rand::seq::index::sample(&mut rand::thread_rng(), segments.len(), amount) {
+                    food[i] = true;
+                }
+                food
+            }
+        };
+
+        for ((row, col), food) in segments.into_iter().zip(food) {
+            self.state_set(
+                row,
+                col,
+                match food {
+                    true => Field::Food(true),
+                    false => Field::Empty,
+                },
+            );
+        }
+    }
+
+    /// Counts down the remains of dead snakes, turning them into food once
+    /// they are done, where nothing else took their place.
+    fn remains_tick(&mut self) {
+        for remains in self.remains.iter_mut() {
+            remains.ticks -= 1;
+        }
+
+        let (done, remains) = std::mem::take(&mut self.remains)
+            .into_iter()
+            .partition(|remains| remains.ticks == 0);
+        self.remains = remains;
+        for Remains { fields, .. } in done {
+            for ((row, col), field) in fields {
+                if self.state[row][col] == field {
+                    self.state_set(row, col, Field::Food(true));
+                }
+            }
+        }
+    }
+
+    /// Updates the fields of the segments of a snake, by their position
+    /// from the tail, where they changed.
+    fn snake_draw(&mut self, id: PlayerId, indices: impl IntoIterator<Item = usize>) {
+        let player = &self.players[&id];
+        let length = player.snake.len();
+        let fields: Vec<_> = indices
+            .into_iter()
+            .filter(|index| *index < length)
+            .map(|index| {
+                let part = match index {
+                    _ if index + 1 == length => Part::Head,
+                    0 => Part::Tail,
+                    _ => Part::Body,
+                };
+                let segment = Segment {
+                    id,
+                    color: player.color,
+                    part,
+                    number: player.tail.wrapping_add(index as u32),
+                };
+                (player.snake[index], Field::Snake(segment))
+            })
+            .collect();
+
+        for ((row, col), field) in fields {
+            if self.state[row][col] != field {
+                self.state_set(row, col, field);
+            }
+        }
+    }
+
+    /// Sets the skin of a client, recoloring its snake if it has one.
+    fn client_skin_set(&mut self, id: PlayerId, mut skin: Skin) {
+        if !skin.valid() {
+            warn!("Player {} can't use skin {:?}", id, skin);
+            self.error(id, ErrorCode::InvalidSkin, "Invalid skin");
+            skin = Skin::default();
+        }
+
+        let client = match self.clients.get_mut(&id) {
+            Some(client) => client,
+            None => return,
+        };
+        client.skin = skin.clone();
+        let name = client.name.clone();
+
+        if let (Some(color), Some(player)) = (skin.color, self.players.get_mut(&id)) {
+            player.color = color;
+            let length = player.snake.len();
+            self.snake_draw(id, 0..length);
+        }
+
+        let _ = self
+            .events
+            .send(ServerMessage::PlayerJoined(PlayerJoinedMessage {
+                id,
+                name,
+                skin,
+            }));
+    }
+
+    pub fn player_direction(&mut self, id: PlayerId, dir: Direction) {
+        if let Some(player) = self.players.get_mut(&id) {
+            if player.direction != dir.opposite() {
+                player.direction = dir;
+            }
+        }
+    }
+
+    /// Random empty field, if there is one.
+    pub fn empty_field(&self) -> Option<(usize, usize)> {
+        let empty: Vec<_> = self
+            .state
+            .iter()
+            .enumerate()
+            .flat_map(|(row, cols)| {
+                cols.iter()
+                    .enumerate()
+                    .filter(|(_, field)| **field == Field::Empty)
+                    .map(move |(col, _)| (row, col))
+            })
+            .collect();
+        empty.choose(&mut rand::thread_rng()).copied()
+    }
+
+    /// Position next to the given one, in the given direction, if it is on
+    /// the board.
+    fn step(&self, (row, col): (usize, usize), dir: Direction) -> Option<(usize, usize)> {
+        let (drow, dcol) = dir.offset();
+        let row = row.checked_add_signed(drow)?;
+        let col = col.checked_add_signed(dcol)?;
+        self.state.get(row)?.get(col)?;
+        Some((row, col))
+    }
+
+    /// Field next to the given position, in the given direction.
+    fn neighbour(&self, position: (usize, usize), dir: Direction) -> Option<Field> {
+        self.step(position, dir)
+            .map(|(row, col)| self.state[row][col])
+    }
+
+    pub fn food_set(&mut self, food: usize) {
+        self.food_target = food;
+        self.food_fill();
+    }
+
+    /// Adds food until there is enough, or the board is full.
+    fn food_fill(&mut self) {
+        while self.food_current < self.food_target && self.food_add() {}
+    }
+
+    /// Puts food on a random empty field, returns false if there is none.
+    fn food_add(&mut self) -> bool {
+        match self.empty_field() {
+            Some((row, col)) => {
+                self.state_set(row, col, Field::Food(false));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn food_renew(&mut self) {
+        if self.food_current < self.food_target {
+            self.food_add();
+        }
+    }
+
+    pub fn bots_set(&mut self, bots: usize) {
+        self.bots_target = bots;
+        self.bots_renew();
+    }
+
+    fn bots_renew(&mut self) {
+        let bots = self.players.values().filter(|player| player.bot).count();
+        for _ in bots..self.bots_target {
+            // only use up the id if the bot fits on the board
+            let id = self.next_id + 1;
+            if !self.player_spawn(id, true) {
+                break;
+            }
+            self.id_next();
+        }
+    }
+
+    /// Turns a bot towards the closest food, avoiding walls and snakes.
+    fn bot_steer(&mut self, id: PlayerId) {
+        let player = &self.players[&id];
+        let head = *player.snake.back().unwrap();
+        let food: Vec<_> = self
+            .state
+            .iter()
+            .enumerate()
+            .flat_map(|(row, cols)| {
+                cols.iter()
+                    .enumerate()
+                    .filter(|(_, field)| field.food())
+                    .map(move |(col, _)| (row, col))
+            })
+            .collect();
+
+        let direction = DIRECTIONS
+            .into_iter()
+            .filter(|dir| *dir != player.direction.opposite())
+            .filter(|dir| {
+                matches!(
+                    self.neighbour(head, *dir),
+                    Some(Field::Empty | Field::Food(_))
+                )
+            })
+            .min_by_key(|dir| {
+                let (drow, dcol) = dir.offset();
+                let next = (head.0 as isize + drow, head.1 as isize + dcol);
+                food.iter()
+                    .map(|(row, col)| {
+                        (next.0 - *row as isize).abs() + (next.1 - *col as isize).abs()
+                    })
+                    .min()
+                    .unwrap_or(0)
+            });
+
+        if let Some(direction) = direction {
+            self.player_direction(id, direction);
+        }
+    }
+
+    pub fn motd_set(&mut self, motd: Option<String>) {
+        self.motd = motd;
+    }
+
+    /// Pauses or resumes the game on behalf of the admin.
+    pub fn pause_set(&mut self, paused: bool) {
+        self.paused = paused;
+        self.pause_update();
+    }
+
+    /// Toggles the pause vote of a player.
+    pub fn pause_vote(&mut self, id: PlayerId) {
+        if !self.pause_votes.remove(&id) {
+            self.pause_votes.insert(id);
+        }
+        info!(
+            "Player {} voted, {} of {} want to pause",
+            id,
+            self.pause_votes.len(),
+            self.clients.len()
+        );
+        self.pause_update();
+    }
+
+    pub fn paused(&self) -> bool {
+        self.pause.is_some()
+    }
+
+    /// Works out if the game should be paused, tells players when that changes.
+    fn pause_update(&mut self) {
+        let pause = if self.paused {
+            Some(PauseReason::Admin)
+        } else if self.clients.is_empty() {
+            Some(PauseReason::Empty)
+        } else if 2 * self.pause_votes.len() > self.clients.len() {
+            Some(PauseReason::Vote)
+        } else {
+            None
+        };
+
+        if pause != self.pause {
+            match pause {
+                Some(reason) => info!("Pausing game ({:?})", reason),
+                None => info!("Resuming game"),
+            }
+            self.pause = pause;
+            let _ = self
+                .events
+                .send(ServerMessage::Pause(PauseMessage { reason: pause }));
+        }
+    }
+
+    /// Removes all snakes and food and restores the map.
+    pub fn reset(&mut self) {
+        let players: Vec<_> = self.players.keys().cloned().collect();
+        for player in players {
+            self.player_finish(player, false);
+            self.notify_state(player, PlayerState::Lost);
+        }
+        for player in std::mem::take(&mut self.spawn_queue) {
+            self.notify_state(player, PlayerState::Lost);
+        }
+        self.remains.clear();
+
+        for (row, cols) in self.map.fields().clone().into_iter().enumerate() {
+            for (col, field) in cols.into_iter().enumerate() {
+                if self.state[row][col] != field {
+                    self.state_set(row, col, field);
+                }
+            }
+        }
+
+        self.food_set(self.food_target);
+        self.bots_renew();
+    }
+
+    fn state_set(&mut self, row: usize, col: usize, field: Field) {
+        // track changes in food supply
+        use Field::*;
+        match (self.state[row][col], field) {
+            (Food(_), x) if !x.food() => self.food_current -= 1,
+            (x, Food(_)) if !x.food() => self.food_current += 1,
+            _ => {}
+        }
+
+        // update field
+        self.state[row][col] = field;
+
+        // send field change event to players, fails if nobody is listening
+        let _ = self
+            .events
+            .send(ServerMessage::FieldChange(FieldChangeMessage {
+                coordinate: Coordinate::new(row, col),
+                field,
+            }));
+    }
+
+    pub fn messages_initial(&self, id: PlayerId) -> Vec<ServerMessage> {
+        let mut messages = vec![ServerMessage::Welcome(WelcomeMessage {
+            id,
+            size: (self.state.len(), self.state[0].len()),
+        })];
+
+        let state = if self.players.contains_key(&id) {
+            PlayerState::Playing
+        } else if self.spawn_queue.contains(&id) {
+            PlayerState::Waiting
+        } else {
+            PlayerState::Lost
+        };
+        messages.push(ServerMessage::PlayerState(PlayerStateMessage {
+            state,
+            death: None,
+        }));
+
+        for (row, cols) in self.state.iter().enumerate() {
+            for (col, field) in cols.iter().enumerate() {
+                if *field != Field::Empty {
+                    messages.push(ServerMessage::FieldChange(FieldChangeMessage {
+                        coordinate: Coordinate::new(row, col),
+                        field: *field,
+                    }));
+                }
+            }
+        }
+
+        messages.push(self.heads());
+        messages.push(self.scores());
+
+        for (id, client) in &self.clients {
+            if client.name.is_some() {
+                messages.push(ServerMessage::PlayerJoined(PlayerJoinedMessage {
+                    id: *id,
+                    name: client.name.clone(),
+                    skin: client.skin.clone(),
+                }));
+            }
+        }
+
+        if self.pause.is_some() {
+            messages.push(ServerMessage::Pause(PauseMessage { reason: self.pause }));
+        }
+
+        if let Some(text) = &self.motd {
+            messages.push(ServerMessage::Motd(MotdMessage { text: text.clone() }));
+        }
+
+        if !self.client_joinable(id) {
+            messages.push(ServerMessage::Auth(AuthMessage::Required));
+        }
+
+        messages
+    }
+
+    pub fn tick(&mut self) {
+        self.ticks += 1;
+        self.mode.on_tick(self.ticks);
+
+        let bots: Vec<_> = self
+            .players
+            .iter()
+            .filter(|(_, player)| player.bot)
+            .map(|(id, _)| *id)
+            .collect();
+        for bot in bots {
+            self.bot_steer(bot);
+        }
+
+        self.remains_tick();
+
+        let players: Vec<_> = self.players.keys().cloned().collect();
+        for player in players {
+            if let Some(death) = self.player_tick(player) {
+                info!("Player {} removed with score {}", player, death.score);
+                let _ = self.events.send(ServerMessage::PlayerDied(death.clone()));
+                self.player_finish(player, false);
+                self.notify(
+                    player,
+                    ServerMessage::PlayerState(PlayerStateMessage {
+                        state: PlayerState::Lost,
+                        death: Some(death),
+                    }),
+                );
+            }
+        }
+
+        if let Some(winner) = self.mode.winner(&self.players) {
+            let score = self.mode.score(&self.players[&winner]);
+            info!("Player {} won with score {}", winner, score);
+            let players: Vec<_> = self.players.keys().cloned().collect();
+            for player in players {
+                self.player_finish(player, player == winner);
+                self.notify_state(
+                    player,
+                    match player == winner {
+                        true => PlayerState::Won,
+                        false => PlayerState::Lost,
+                    },
+                );
+            }
+        }
+
+        // queued players get free fields before bots, a full board is only
+        // searched again after a while
+        if self.ticks >= self.spawn_retry {
+            self.spawn_queued();
+            self.bots_renew();
+        }
+
+        let _ = self.events.send(self.heads());
+        let _ = self.events.send(self.scores());
+    }
+
+    /// Positions of the heads of all snakes.
+    fn heads(&self) -> ServerMessage {
+        let heads = self
+            .players
+            .iter()
+            .filter_map(|(id, player)| {
+                let (row, col) = *player.snake.back()?;
+                Some((*id, Coordinate::new(row, col)))
+            })
+            .collect();
+        ServerMessage::Heads(HeadsMessage { heads })
+    }
+
+    /// Scores of all snakes.
+    fn scores(&self) -> ServerMessage {
+        let scores = self
+            .players
+            .iter()
+            .map(|(id, player)| (*id, self.mode.score(player)))
+            .collect();
+        ServerMessage::Scores(ScoresMessage { scores })
+    }
+
+    fn death(
+        &self,
+        id: PlayerId,
+        killer: Option<PlayerId>,
+        cause: DeathCause,
+    ) -> PlayerDiedMessage {
+        let name = |id| self.clients.get(&id).and_then(|client| client.name.clone());
+        PlayerDiedMessage {
+            id,
+            name: name(id),
+            killer,
+            killer_name: killer.and_then(name),
+            cause,
+            score: self.mode.score(&self.players[&id]),
+        }
+    }
+
+    /// Moves a snake ahead, returns how it died if it did.
+    pub fn player_tick(&mut self, id: PlayerId) -> Option<PlayerDiedMessage> {
+        let player = self.players.get_mut(&id).unwrap();
+        let protected = player.protection > 0;
+        player.protection = player.protection.saturating_sub(1);
+        let head = player.snake.back().unwrap();
+        let dir = player.direction.offset();
+        let next = (dir.0 + head.0 as isize, dir.1 + head.1 as isize);
+        let element = self
+            .state
+            .get(next.0 as usize)
+            .and_then(|r| r.get(next.1 as usize));
+
+        let element = match element {
+            Some(value) => *value,
+            None => {
+                info!("Player {} left playing field", id);
+                return Some(self.death(id, None, DeathCause::Edge));
+            }
+        };
+
+        match element {
+            // freshly spawned snakes wait instead of dying
+            Field::Wall | Field::Snake(_) if protected => {
+                debug!("Player {} is protected from collision", id);
+                return None;
+            }
+            Field::Wall | Field::Snake(_) => {
+                if self.mode.on_collision(id, element) {
+                    // credit the kill to the owner of the snake
+                    let position = (next.0 as usize, next.1 as usize);
+                    let owner = self.snake_at(position);
+                    let killer = owner.filter(|killer| *killer != id);
+                    if let Some(killer) = killer {
+                        self.players.get_mut(&killer).unwrap().kills += 1;
+                    }
+                    let cause = match element {
+                        Field::Wall => DeathCause::Wall,
+                        _ if owner == Some(id) => DeathCause::Itself,
+                        _ => DeathCause::Snake,
+                    };
+                    info!("Player {} died: {}", id, cause);
+                    return Some(self.death(id, killer, cause));
+                }
+            }
+            Field::Food(rotten) => {
+                info!("Player {} got food", id);
+                let growth = self.mode.on_food(id, rotten);
+                self.players.get_mut(&id).unwrap().growth += growth;
+            }
+            Field::Empty => {}
+        }
+
+        info!("Player {} moves to ({}, {})", id, next.0, next.1);
+        let player = self.players.get_mut(&id).unwrap();
+        player.snake.push_back((next.0 as usize, next.1 as usize));
+        let last = match player.growth {
+            0 => {
+                player.tail = player.tail.wrapping_add(1);
+                player.snake.pop_front()
+            }
+            _ => {
+                player.growth -= 1;
+                None
+            }
+        };
+        let length = player.snake.len();
+
+        if let Some((row, col)) = last {
+            self.state_set(row, col, Field::Empty);
+        }
+        // the new head, the old head and the tail changed
+        self.snake_draw(id, [0, length.saturating_sub(2), length - 1]);
+        if element.food() {
+            self.food_renew();
+        }
+
+        None
+    }
+
+    pub fn handle(&mut self, id: PlayerId, message: &ClientMessage) {
+        // the client may have left since sending the message
+        if !self.clients.contains_key(&id) {
+            return;
+        }
+
+        use ClientMessage::*;
+        match message {
+            Direction(dir) => self.player_direction(id, dir.direction),
+            Restart if !self.client_joinable(id) => {
+                self.error(id, ErrorCode::NotAuthenticated, "Log in to play")
+            }
+            Restart if !self.player_exists(id) => self.player_add(id),
+            Hello(hello) => match self.client_name_set(id, hello.name.clone()) {
+                Ok(()) => {
+                    info!("Player {} is called {}", id, hello.name);
+                    self.client_skin_set(id, hello.skin.clone());
+                }
+                Err(e) => {
+                    warn!("Player {} can't use name {:?}: {}", id, hello.name, e);
+                    self.client_disconnect(id, e);
+                }
+            },
+            Pause => self.pause_vote(id),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mode;
+
+    /// Board with a single row of five free fields.
+    const CORRIDOR: &str = "#######\n#.....#\n#######";
+
+    fn game(map: &str) -> Game {
+        Game::new(Map::parse(map).unwrap(), mode::mode("classic").unwrap())
+    }
+
+    fn client(game: &mut Game) -> PlayerId {
+        let (id, _, _) = game.client_add(([127, 0, 0, 1], 0).into()).unwrap();
+        id
+    }
+
+    #[test]
+    fn classic_score_and_winner() {
+        let mut game = game(CORRIDOR);
+        let id = client(&mut game);
+        game.player_add(id);
+        game.players.get_mut(&id).unwrap().growth = 2;
+        game.tick();
+        game.tick();
+
+        // the score is the length, nobody wins in classic
+        let player = &game.players[&id];
+        assert_eq!(player.length(), 3);
+        assert_eq!(game.mode.score(player), 3);
+        assert_eq!(game.mode.winner(&game.players), None);
+    }
+
+    #[test]
+    fn client_login() {
+        let mut game = game(CORRIDOR);
+        game.auth_required_set(true);
+        let first = client(&mut game);
+        let second = client(&mut game);
+        assert!(!game.client_joinable(first));
+
+        game.client_login(first, "alice".into()).unwrap();
+        assert!(game.client_joinable(first));
+        assert!(game.player_exists(first));
+        assert_eq!(
+            game.client_login(first, "bob".into())
+                .unwrap_err()
+                .to_string(),
+            "Already logged in"
+        );
+        assert_eq!(
+            game.client_login(second, "alice".into())
+                .unwrap_err()
+                .to_string(),
+            "Account is already playing"
+        );
+        assert!(!game.client_joinable(second));
+    }
+
+    #[test]
+    fn client_add_refusals() {
+        let mut game = game(CORRIDOR);
+        game.clients_max_set(2);
+        game.clients_per_address_set(1);
+        let address: SocketAddr = ([127, 0, 0, 1], 0).into();
+        game.client_add(address).unwrap();
+        assert_eq!(
+            game.client_add(address).unwrap_err(),
+            DisconnectReason::TooManyConnections
+        );
+        game.client_add(([127, 0, 0, 2], 0).into()).unwrap();
+        assert_eq!(
+            game.client_add(([127, 0, 0, 3], 0).into()).unwrap_err(),
+            DisconnectReason::ServerFull
+        );
+        game.ban([127, 0, 0, 4].into());
+        assert_eq!(
+            game.client_add(([127, 0, 0, 4], 0).into()).unwrap_err(),
+            DisconnectReason::Banned
+        );
+    }
+
+    #[test]
+    fn client_disconnect_and_error() {
+        let mut game = game(CORRIDOR);
+        let (id, _, mut messages) = game.client_add(([127, 0, 0, 1], 0).into()).unwrap();
+        game.error(id, ErrorCode::InvalidSkin, "Bad skin");
+        match messages.try_recv().unwrap() {
+            ServerMessage::Error(error) => {
+                assert_eq!(error.code, ErrorCode::InvalidSkin);
+                assert_eq!(error.message, "Bad skin");
+            }
+            message => panic!("Unexpected message {:?}", message),
+        }
+
+        assert!(game.client_disconnect(id, DisconnectReason::Kicked));
+        match messages.try_recv().unwrap() {
+            ServerMessage::Disconnect(disconnect) => {
+                assert_eq!(disconnect.reason, DisconnectReason::Kicked)
+            }
+            message => panic!("Unexpected message {:?}", message),
+        }
+        assert!(!game.client_disconnect(id, DisconnectReason::Kicked));
+    }
+
+    #[test]
+    fn pause_when_empty() {
+        let mut game = game(CORRIDOR);
+        assert_eq!(game.pause, Some(PauseReason::Empty));
+        let id = client(&mut game);
+        assert_eq!(game.pause, None);
+        game.client_remove(id);
+        assert_eq!(game.pause, Some(PauseReason::Empty));
+    }
+
+    #[test]
+    fn pause_by_majority() {
+        let mut game = game(CORRIDOR);
+        let first = client(&mut game);
+        let second = client(&mut game);
+        let third = client(&mut game);
+
+        game.pause_vote(first);
+        assert_eq!(game.pause, None);
+        game.pause_vote(second);
+        assert_eq!(game.pause, Some(PauseReason::Vote));
+
+        // voting again takes the vote back
+        game.pause_vote(second);
+        assert_eq!(game.pause, None);
+
+        // votes count against the players still there
+        game.client_remove(second);
+        game.client_remove(third);
+        assert_eq!(game.pause, Some(PauseReason::Vote));
+    }
+
+    #[test]
+    fn pause_by_admin() {
+        let mut game = game(CORRIDOR);
+        let id = client(&mut game);
+        game.pause_set(true);
+        assert_eq!(game.pause, Some(PauseReason::Admin));
+        game.pause_vote(id);
+        assert_eq!(game.pause, Some(PauseReason::Admin));
+        game.pause_set(false);
+        assert_eq!(game.pause, Some(PauseReason::Vote));
+    }
+
+    #[test]
+    fn spawn_prefers_longest_runway() {
+        let game = game(CORRIDOR);
+        for _ in 0..20 {
+            let spawn = game.spawn_position();
+            assert!(
+                spawn == Some(((1, 1), Direction::Right))
+                    || spawn == Some(((1, 5), Direction::Left)),
+                "{:?}",
+                spawn
+            );
+        }
+    }
+
+    #[test]
+    fn spawn_runway_is_capped() {
+        let mut game = game(CORRIDOR);
+        game.spawn_set(Spawn {
+            runway: 2,
+            ..Spawn::default()
+        });
+        // every field has two free fields ahead one way or the other
+        let mut positions = BTreeSet::new();
+        for _ in 0..200 {
+            let (position, dir) = game.spawn_position().unwrap();
+            assert!(game.runway(position, dir, 2) == 2);
+            positions.insert(position);
+        }
+        assert_eq!(positions.len(), 5);
+    }
+
+    #[test]
+    fn spawn_needs_runway() {
+        let game = game("###\n#.#\n###");
+        assert_eq!(game.spawn_position(), None);
+    }
+
+    #[test]
+    fn spawn_queues_when_full() {
+        let mut game = game("####\n#..#\n####");
+        let first = client(&mut game);
+        let second = client(&mut game);
+
+        game.player_add(first);
+        assert!(game.player_exists(first));
+
+        // the only other field is next to the first snake
+        game.player_add(second);
+        assert!(!game.player_exists(second));
+        assert_eq!(game.spawn_queue, [second]);
+
+        // queueing again doesn't add the player twice
+        game.player_add(second);
+        assert_eq!(game.spawn_queue, [second]);
+
+        game.player_remove(first);
+        game.spawn_queued();
+        assert!(game.player_exists(second));
+        assert!(game.spawn_queue.is_empty());
+    }
+
+    #[test]
+    fn reset_finishes_players() {
+        let mut game = game("####\n#..#\n####");
+        let (results, mut finished) = unbounded_channel();
+        game.results_set(results);
+        let first = client(&mut game);
+        let second = client(&mut game);
+        // logging in lets the player join
+        game.client_login(first, "alice".into()).unwrap();
+        assert!(game.player_exists(first));
+        game.player_add(second);
+        assert_eq!(game.spawn_queue, [second]);
+
+        game.reset();
+        assert!(!game.player_exists(first));
+        assert!(game.spawn_queue.is_empty());
+        let result = finished.try_recv().unwrap();
+        assert_eq!(result.name, "alice");
+        assert!(!result.won);
+    }
+
+    #[test]
+    fn spawn_retries_later() {
+        let mut game = game("###\n#.#\n###");
+        let id = client(&mut game);
+        game.player_add(id);
+        assert_eq!(game.spawn_queue, [id]);
+        assert_eq!(game.spawn_retry, SPAWN_RETRY_TICKS);
+
+        // the board isn't searched again until the retry tick
+        for _ in 1..SPAWN_RETRY_TICKS {
+            game.tick();
+        }
+        assert_eq!(game.spawn_retry, SPAWN_RETRY_TICKS);
+        game.tick();
+        assert_eq!(game.spawn_retry, 2 * SPAWN_RETRY_TICKS);
+    }
+
+    #[test]
+    fn spawn_faces_away_from_walls() {
+        let game = game("###\n#.#\n#.#\n#.#\n###");
+        for _ in 0..20 {
+            let spawn = game.spawn_position();
+            assert!(
+                spawn == Some(((1, 1), Direction::Down)) || spawn == Some(((3, 1), Direction::Up)),
+                "{:?}",
+                spawn
+            );
+        }
+    }
+
+    #[test]
+    fn spawn_point_sets_direction() {
+        let game = game("#####\n#>..#\n#####");
+        assert_eq!(game.spawn_position(), Some(((1, 1), Direction::Right)));
+    }
+
+    #[test]
+    fn spawn_point_facing_wall_is_unused() {
+        let game = game("#####\n#<..#\n#####");
+        assert_eq!(game.spawn_position(), None);
+    }
+
+    #[test]
+    fn spawn_with_initial_length() {
+        let mut game = game(CORRIDOR);
+        game.spawn_set(Spawn {
+            length: 3,
+            ..Spawn::default()
+        });
+        let id = client(&mut game);
+        game.player_add(id);
+
+        // the body needs two free fields behind the head, which leaves the
+        // middle as the only place with room ahead
+        let player = &game.players[&id];
+        let snake: Vec<_> = player.snake.iter().copied().collect();
+        match player.direction {
+            Direction::Right => assert_eq!(snake, [(1, 1), (1, 2), (1, 3)]),
+            Direction::Left => assert_eq!(snake, [(1, 5), (1, 4), (1, 3)]),
+            dir => panic!("Snake faces {:?}", dir),
+        }
+    }
+
+    /// Fields of the corridor after a snake along it died.
+    fn corpse(kind: CorpseKind, percent: u8, ticks: u64) -> (Game, Vec<Field>) {
+        let mut game = game(CORRIDOR);
+        game.corpse_set(Corpse {
+            kind,
+            percent,
+            ticks,
+        });
+        game.corpse_add((1..=4).map(|col| (1, col)).collect());
+        let fields = game.state[1][1..=4].to_vec();
+        (game, fields)
+    }
+
+    #[test]
+    fn corpse_food() {
+        let (game, fields) = corpse(CorpseKind::Food, 50, 20);
+        assert_eq!(fields, [Field::Food(true); 4]);
+        assert_eq!(game.food_current, 4);
+    }
+
+    #[test]
+    fn corpse_alternate() {
+        let (_, fields) = corpse(CorpseKind::Alternate, 50, 20);
+        use Field::*;
+        assert_eq!(fields, [Food(true), Empty, Food(true), Empty]);
+    }
+
+    #[test]
+    fn corpse_percent() {
+        let (_, fields) = corpse(CorpseKind::Percent, 0, 20);
+        assert_eq!(fields, [Field::Empty; 4]);
+
+        let (_, fields) = corpse(CorpseKind::Percent, 100, 20);
+        assert_eq!(fields, [Field::Food(true); 4]);
+
+        let (game, fields) = corpse(CorpseKind::Percent, 50, 20);
+        assert_eq!(fields.iter().filter(|field| field.food()).count(), 2);
+        assert_eq!(game.food_current, 2);
+    }
+
+    #[test]
+    fn corpse_decay() {
+        let mut game = game(CORRIDOR);
+        game.corpse_set(Corpse {
+            kind: CorpseKind::Decay,
+            percent: 50,
+            ticks: 2,
+        });
+        // stands in for the segment of the dead snake
+        game.state[1][1] = Field::Wall;
+        game.corpse_add(vec![(1, 1)]);
+
+        // the remains stay as they are until they decay
+        game.remains_tick();
+        assert_eq!(game.state[1][1], Field::Wall);
+        game.remains_tick();
+        assert_eq!(game.state[1][1], Field::Food(true));
+        assert!(game.remains.is_empty());
+    }
+
+    #[test]
+    fn corpse_decay_keeps_changed_fields() {
+        let mut game = game(CORRIDOR);
+        game.corpse_set(Corpse {
+            kind: CorpseKind::Decay,
+            percent: 50,
+            ticks: 1,
+        });
+        game.state[1][1] = Field::Wall;
+        game.state[1][2] = Field::Wall;
+        game.corpse_add(vec![(1, 1), (1, 2)]);
+
+        // something else took the place of a segment before it decayed
+        game.state[1][2] = Field::Food(false);
+        game.remains_tick();
+        assert_eq!(game.state[1][1], Field::Food(true));
+        assert_eq!(game.state[1][2], Field::Food(false));
+    }
+
+    #[test]
+    fn corpse_decay_without_ticks() {
+        let (game, fields) = corpse(CorpseKind::Decay, 50, 0);
+        assert_eq!(fields, [Field::Food(true); 4]);
+        assert!(game.remains.is_empty());
+    }
+
+    /// Open board of three rows by five columns.
+    const OPEN: &str = "#######\n#.....#\n#.....#\n#.....#\n#######";
+
+    /// Puts an unprotected snake on the board, from tail to head.
+    fn snake(game: &mut Game, segments: &[(usize, usize)], direction: Direction) -> PlayerId {
+        let id = client(game);
+        game.players.insert(
+            id,
+            Player {
+                snake: segments.iter().copied().collect(),
+                color: Color { r: 0, g: 0, b: 0 },
+                tail: 0,
+                direction,
+                growth: 0,
+                kills: 0,
+                bot: false,
+                protection: 0,
+            },
+        );
+        game.snake_draw(id, 0..segments.len());
+        id
+    }
+
+    #[test]
+    fn death_by_snake_credits_killer() {
+        let mut game = game(OPEN);
+        let victim = snake(&mut game, &[(1, 2)], Direction::Down);
+        let killer = snake(&mut game, &[(2, 1), (2, 2), (2, 3)], Direction::Right);
+
+        let death = game.player_tick(victim).unwrap();
+        assert_eq!(death.cause, DeathCause::Snake);
+        assert_eq!(death.killer, Some(killer));
+        let player = &game.players[&killer];
+        assert_eq!(player.kills(), 1);
+        // three segments and five points for the kill
+        assert_eq!(game.mode.score(player), 8);
+    }
+
+    #[test]
+    fn death_by_itself() {
+        let mut game = game(OPEN);
+        let id = snake(&mut game, &[(1, 1), (1, 2), (2, 2), (2, 1)], Direction::Up);
+
+        let death = game.player_tick(id).unwrap();
+        assert_eq!(death.cause, DeathCause::Itself);
+        assert_eq!(death.killer, None);
+        assert_eq!(game.players[&id].kills(), 0);
+    }
+
+    #[test]
+    fn death_by_wall() {
+        let mut game = game(OPEN);
+        let id = snake(&mut game, &[(1, 1)], Direction::Left);
+
+        let death = game.player_tick(id).unwrap();
+        assert_eq!(death.cause, DeathCause::Wall);
+        assert_eq!(death.killer, None);
+    }
+}
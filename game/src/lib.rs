@@ -0,0 +1,10 @@
+mod game;
+mod map;
+pub mod mode;
+mod palette;
+mod settings;
+
+pub use game::{Game, GameResult, Player, PlayerStats, Stats, ROOM};
+pub use map::{Map, SpawnPoint};
+pub use mode::GameMode;
+pub use settings::{Corpse, CorpseKind, Spawn};
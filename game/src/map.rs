@@ -0,0 +1,176 @@
+use anyhow::{anyhow, Context, Result};
+use std::path::Path;
+use xenopeltis_common::*;
+
+/// Layout of the board.
+///
+/// Maps are plain text files, where every line is a row of the board and
+/// every character is a field: `#` is a wall, `S` is an empty field where
+/// snakes may spawn, `^`, `v`, `<` and `>` are spawn points where snakes
+/// face that way, anything else is empty. Short lines are padded with empty
+/// fields. Without spawn points, snakes spawn anywhere.
+#[derive(Clone, Debug)]
+pub struct Map {
+    fields: Vec<Vec<Field>>,
+    spawns: Vec<SpawnPoint>,
+}
+
+/// Position where snakes may spawn.
+#[derive(Clone, Copy, Debug)]
+pub struct SpawnPoint {
+    pub position: (usize, usize),
+    /// Direction new snakes face, chosen by the game if not set.
+    pub direction: Option<Direction>,
+}
+
+impl Map {
+    /// Empty map surrounded by walls, at least 3x3 so there is room inside.
+    pub fn new(rows: usize, cols: usize) -> Result<Self> {
+        if rows < 3 || cols < 3 {
+            return Err(anyhow!("Board must be at least 3x3"));
+        }
+
+        let mut fields = vec![vec![Field::Empty; cols]; rows];
+
+        // draw walls
+        for row in fields.iter_mut() {
+            row[0] = Field::Wall;
+            row[cols - 1] = Field::Wall;
+        }
+
+        fields[0].fill(Field::Wall);
+        fields[rows - 1].fill(Field::Wall);
+
+        Ok(Map {
+            fields,
+            spawns: vec![],
+        })
+    }
+
+    pub fn parse(data: &str) -> Result<Self> {
+        let mut spawns = vec![];
+        let mut fields: Vec<Vec<Field>> = data
+            .lines()
+            .enumerate()
+            .map(|(row, line)| {
+                line.chars()
+                    .enumerate()
+                    .map(|(col, c)| {
+                        let direction = match c {
+                            '#' => return Field::Wall,
+                            'S' => None,
+                            '^' => Some(Direction::Up),
+                            'v' => Some(Direction::Down),
+                            '<' => Some(Direction::Left),
+                            '>' => Some(Direction::Right),
+                            _ => return Field::Empty,
+                        };
+                        spawns.push(SpawnPoint {
+                            position: (row, col),
+                            direction,
+                        });
+                        Field::Empty
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let cols = fields.iter().map(|row| row.len()).max().unwrap_or(0);
+        if cols == 0 {
+            return Err(anyhow!("Map is empty"));
+        }
+
+        for row in fields.iter_mut() {
+            row.resize(cols, Field::Empty);
+        }
+
+        Ok(Map { fields, spawns })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Reading map {}", path.display()))?;
+        Map::parse(&data).with_context(|| format!("Parsing map {}", path.display()))
+    }
+
+    pub fn fields(&self) -> &Vec<Vec<Field>> {
+        &self.fields
+    }
+
+    /// Positions where snakes may spawn, empty if they can spawn anywhere.
+    pub fn spawns(&self) -> &[SpawnPoint] {
+        &self.spawns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_walls_and_padding() {
+        let map = Map::parse("###\n#\n# #").unwrap();
+        assert_eq!(
+            map.fields(),
+            &vec![
+                vec![Field::Wall, Field::Wall, Field::Wall],
+                vec![Field::Wall, Field::Empty, Field::Empty],
+                vec![Field::Wall, Field::Empty, Field::Wall],
+            ]
+        );
+        assert!(map.spawns().is_empty());
+    }
+
+    #[test]
+    fn parse_spawn_points() {
+        let map = Map::parse("S^\nv<>").unwrap();
+        let spawns: Vec<_> = map
+            .spawns()
+            .iter()
+            .map(|spawn| (spawn.position, spawn.direction))
+            .collect();
+        assert_eq!(
+            spawns,
+            vec![
+                ((0, 0), None),
+                ((0, 1), Some(Direction::Up)),
+                ((1, 0), Some(Direction::Down)),
+                ((1, 1), Some(Direction::Left)),
+                ((1, 2), Some(Direction::Right)),
+            ]
+        );
+
+        // spawn points are empty fields
+        assert!(map
+            .fields()
+            .iter()
+            .flatten()
+            .all(|field| *field == Field::Empty));
+    }
+
+    #[test]
+    fn parse_empty_fails() {
+        assert!(Map::parse("").is_err());
+        assert!(Map::parse("\n\n").is_err());
+    }
+
+    #[test]
+    fn new_has_walls_around() {
+        let map = Map::new(3, 4).unwrap();
+        let fields = map.fields();
+        assert_eq!(fields.len(), 3);
+        assert!(fields[0].iter().all(|field| *field == Field::Wall));
+        assert!(fields[2].iter().all(|field| *field == Field::Wall));
+        assert_eq!(
+            fields[1],
+            vec![Field::Wall, Field::Empty, Field::Empty, Field::Wall]
+        );
+    }
+
+    #[test]
+    fn new_too_small() {
+        assert!(Map::new(2, 10).is_err());
+        assert!(Map::new(10, 2).is_err());
+        assert!(Map::new(0, 0).is_err());
+    }
+}
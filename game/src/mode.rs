@@ -0,0 +1,91 @@
+use crate::game::Player;
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use xenopeltis_common::*;
+
+/// Rules of the game.
+///
+/// The game loop calls into these hooks to decide what happens when snakes
+/// join, move, eat or collide. Implement this to add a new rule set.
+pub trait GameMode: Debug + Send {
+    /// Name of this mode, as used on the command line.
+    fn name(&self) -> &'static str;
+
+    /// Called when a player joins the game.
+    fn on_join(&mut self, _id: PlayerId) {}
+
+    /// Called at the start of every tick, before any snake moves.
+    fn on_tick(&mut self, _tick: u64) {}
+
+    /// Called when a snake runs into a wall or another snake, returns true if
+    /// the snake dies. Otherwise it moves on as if the field was empty.
+    fn on_collision(&mut self, id: PlayerId, field: Field) -> bool;
+
+    /// Called when a snake eats food, returns how many segments it grows.
+    fn on_food(&mut self, id: PlayerId, rotten: bool) -> usize;
+
+    /// Checks if there is a winner, which ends the round.
+    fn winner(&self, players: &BTreeMap<PlayerId, Player>) -> Option<PlayerId>;
+
+    /// Score of a player.
+    fn score(&self, player: &Player) -> usize;
+}
+
+/// Points a kill is worth, on top of the length of the snake.
+const KILL_SCORE: usize = 5;
+
+/// Classic snake: walls and snakes kill, food grows you by one, no winner.
+/// The score is the length of the snake plus points for kills.
+#[derive(Clone, Debug, Default)]
+pub struct Classic;
+
+impl GameMode for Classic {
+    fn name(&self) -> &'static str {
+        "classic"
+    }
+
+    fn on_collision(&mut self, _id: PlayerId, _field: Field) -> bool {
+        true
+    }
+
+    fn on_food(&mut self, _id: PlayerId, _rotten: bool) -> usize {
+        1
+    }
+
+    fn winner(&self, _players: &BTreeMap<PlayerId, Player>) -> Option<PlayerId> {
+        None
+    }
+
+    fn score(&self, player: &Player) -> usize {
+        player.length() + KILL_SCORE * player.kills()
+    }
+}
+
+/// Looks up a game mode by name.
+pub fn mode(name: &str) -> Result<Box<dyn GameMode>> {
+    match name {
+        "classic" => Ok(Box::new(Classic)),
+        _ => Err(anyhow!("Unknown game mode {}", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup() {
+        assert_eq!(mode("classic").unwrap().name(), "classic");
+        assert!(mode("battle").is_err());
+    }
+
+    #[test]
+    fn classic_rules() {
+        let mut classic = Classic;
+        assert!(classic.on_collision(1, Field::Wall));
+        assert_eq!(classic.on_food(1, false), 1);
+        assert_eq!(classic.on_food(1, true), 1);
+        assert_eq!(classic.winner(&BTreeMap::new()), None);
+    }
+}
@@ -0,0 +1,76 @@
+use rand::seq::SliceRandom;
+use xenopeltis_common::Color;
+
+/// Hues snakes can get, in degrees.
+const HUES: usize = 36;
+
+/// Shades of every hue as saturation and value: bright, dark and pale.
+const SHADES: [(f64, f64); 3] = [(0.85, 1.0), (0.8, 0.75), (0.45, 1.0)];
+
+/// Colors snakes can get.
+fn candidates() -> Vec<Color> {
+    SHADES
+        .iter()
+        .flat_map(|(saturation, value)| {
+            (0..HUES)
+                .map(move |hue| Color::hsv(hue as f64 * 360.0 / HUES as f64, *saturation, *value))
+        })
+        .collect()
+}
+
+/// Picks the color that looks most different from the ones in use, choosing
+/// randomly between equally good ones.
+pub fn distinct(used: &[Color]) -> Color {
+    let scored: Vec<(Color, f64)> = candidates()
+        .into_iter()
+        .map(|color| {
+            let distance = used
+                .iter()
+                .map(|other| color.distance(other))
+                .fold(f64::INFINITY, f64::min);
+            (color, distance)
+        })
+        .collect();
+    let best = scored
+        .iter()
+        .map(|(_, distance)| *distance)
+        .fold(0.0, f64::max);
+    let best: Vec<Color> = scored
+        .into_iter()
+        .filter(|(_, distance)| *distance >= best)
+        .map(|(color, _)| color)
+        .collect();
+    *best.choose(&mut rand::thread_rng()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distinct_without_used_colors() {
+        let color = distinct(&[]);
+        assert!(candidates().contains(&color));
+    }
+
+    #[test]
+    fn distinct_is_farthest_from_used() {
+        let used = [Color::new(255, 0, 0), Color::new(0, 255, 0)];
+        let distance = |color: &Color| {
+            used.iter()
+                .map(|other| color.distance(other))
+                .fold(f64::INFINITY, f64::min)
+        };
+        let best = candidates().iter().map(distance).fold(0.0, f64::max);
+        for _ in 0..20 {
+            assert_eq!(distance(&distinct(&used)), best);
+        }
+    }
+
+    #[test]
+    fn distinct_picks_the_unused_one() {
+        let mut used = candidates();
+        let free = used.remove(7);
+        assert_eq!(distinct(&used), free);
+    }
+}
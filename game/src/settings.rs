@@ -0,0 +1,59 @@
+use serde::Deserialize;
+
+/// How new snakes are put on the board.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Spawn {
+    /// Segments of a new snake, laid out in a straight line behind the
+    /// head.
+    pub length: usize,
+    /// Free fields a new snake wants ahead of it, fewer are used if there
+    /// is no such place.
+    pub runway: usize,
+    /// Ticks in which a new snake stops instead of dying on collision.
+    pub protection: u64,
+}
+
+/// What dead snakes leave behind.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Corpse {
+    pub kind: CorpseKind,
+    /// Share of segments that turn into food, for the percent kind.
+    pub percent: u8,
+    /// Ticks the corpse stays an obstacle, for the decay kind.
+    pub ticks: u64,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CorpseKind {
+    /// Every segment turns into food.
+    Food,
+    /// Every other segment turns into food.
+    Alternate,
+    /// Randomly chosen segments turn into food.
+    Percent,
+    /// The snake stays as an obstacle, then turns into food.
+    Decay,
+}
+
+impl Default for Spawn {
+    fn default() -> Self {
+        Spawn {
+            length: 1,
+            runway: 5,
+            protection: 5,
+        }
+    }
+}
+
+impl Default for Corpse {
+    fn default() -> Self {
+        Corpse {
+            kind: CorpseKind::Food,
+            percent: 50,
+            ticks: 20,
+        }
+    }
+}
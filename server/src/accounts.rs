@@ -0,0 +1,125 @@
+use crate::database::now;
+use anyhow::{anyhow, Result};
+use argon2::password_hash::{rand_core::OsRng, SaltString};
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use xenopeltis_common::*;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS accounts (
+    name TEXT PRIMARY KEY,
+    password TEXT NOT NULL,
+    created INTEGER NOT NULL
+);
+";
+
+/// Player accounts with argon2-hashed passwords.
+#[derive(Clone, Debug)]
+pub struct Accounts {
+    pool: SqlitePool,
+}
+
+fn hash(password: String) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow!("Hashing password: {}", e))?;
+    Ok(hash.to_string())
+}
+
+fn verify(password: String, hash: String) -> bool {
+    match PasswordHash::new(&hash) {
+        Ok(hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+impl Accounts {
+    pub async fn new(pool: SqlitePool) -> Result<Self> {
+        sqlx::query(SCHEMA).execute(&pool).await?;
+        Ok(Accounts { pool })
+    }
+
+    pub async fn register(&self, name: &str, password: &str) -> Result<()> {
+        if !name_valid(name) {
+            return Err(anyhow!("Invalid name"));
+        }
+
+        if password.chars().count() < PASSWORD_LENGTH_MIN {
+            return Err(anyhow!(
+                "Password must have at least {} characters",
+                PASSWORD_LENGTH_MIN
+            ));
+        }
+
+        // hashing is slow on purpose, keep it off the runtime threads
+        let password = password.to_string();
+        let hash = tokio::task::spawn_blocking(move || hash(password)).await??;
+
+        let result = sqlx::query(
+            "INSERT INTO accounts (name, password, created) VALUES (?, ?, ?)
+            ON CONFLICT (name) DO NOTHING",
+        )
+        .bind(name)
+        .bind(hash)
+        .bind(now())
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(anyhow!("Name is already taken"));
+        }
+
+        Ok(())
+    }
+
+    pub async fn login(&self, name: &str, password: &str) -> Result<()> {
+        let row = sqlx::query("SELECT password FROM accounts WHERE name = ?")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        let hash: String = match row {
+            Some(row) => row.get(0),
+            None => return Err(anyhow!("Wrong name or password")),
+        };
+
+        let password = password.to_string();
+        if !tokio::task::spawn_blocking(move || verify(password, hash)).await? {
+            return Err(anyhow!("Wrong name or password"));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+
+    #[tokio::test]
+    async fn register_and_login() {
+        let path =
+            std::env::temp_dir().join(format!("xenopeltis-accounts-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let accounts = Accounts::new(database::open(&path).await.unwrap())
+            .await
+            .unwrap();
+
+        assert!(accounts.register("not a name", "password").await.is_err());
+        assert!(accounts.register("alice", "short").await.is_err());
+        accounts.register("alice", "password").await.unwrap();
+        assert!(accounts.register("alice", "another").await.is_err());
+
+        accounts.login("alice", "password").await.unwrap();
+        let wrong = accounts.login("alice", "passwort").await.unwrap_err();
+        let unknown = accounts.login("bob", "password").await.unwrap_err();
+        // both fail the same way, so names can't be probed
+        assert_eq!(wrong.to_string(), unknown.to_string());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
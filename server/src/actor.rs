@@ -0,0 +1,217 @@
+use crate::config::Config;
+use crate::metrics;
+use anyhow::{anyhow, Result};
+use log::*;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{oneshot, watch};
+use xenopeltis_common::*;
+use xenopeltis_game::Game;
+
+/// A client that joined the game.
+pub struct Joined {
+    pub id: PlayerId,
+    pub events: Receiver<ServerMessage>,
+    pub private: UnboundedReceiver<ServerMessage>,
+    /// Messages that bring the client up to date with the game.
+    pub initial: Vec<ServerMessage>,
+}
+
+enum Command {
+    Join {
+        address: SocketAddr,
+        reply: oneshot::Sender<Result<Joined, DisconnectReason>>,
+    },
+    Leave {
+        id: PlayerId,
+        reason: Option<DisconnectReason>,
+    },
+    Input {
+        id: PlayerId,
+        message: ClientMessage,
+    },
+    Call(Box<dyn FnOnce(&mut Game) + Send>),
+}
+
+/// Sends commands to the task that owns the game.
+#[derive(Clone)]
+pub struct GameHandle {
+    commands: UnboundedSender<Command>,
+}
+
+impl GameHandle {
+    fn send(&self, command: Command) {
+        // the game task only stops once all handles are gone
+        let _ = self.commands.send(command);
+    }
+
+    /// Adds a client and lets it play, if it may.
+    pub async fn join(&self, address: SocketAddr) -> Result<Joined, DisconnectReason> {
+        let (reply, response) = oneshot::channel();
+        self.send(Command::Join { address, reply });
+        response.await.unwrap_or(Err(DisconnectReason::Shutdown))
+    }
+
+    /// Removes a client, telling it why if there is a reason.
+    pub fn leave(&self, id: PlayerId, reason: Option<DisconnectReason>) {
+        self.send(Command::Leave { id, reason });
+    }
+
+    /// Queues a message from a client, which is handled before the next tick.
+    pub fn input(&self, id: PlayerId, message: ClientMessage) {
+        self.send(Command::Input { id, message });
+    }
+
+    /// Sends a message to a single client.
+    pub fn notify(&self, id: PlayerId, message: ServerMessage) {
+        self.send(Command::Call(Box::new(move |game| {
+            game.notify(id, message)
+        })));
+    }
+
+    /// Runs a function on the game and returns its result.
+    pub async fn call<R, F>(&self, function: F) -> Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Game) -> R + Send + 'static,
+    {
+        let (reply, response) = oneshot::channel();
+        self.send(Command::Call(Box::new(move |game| {
+            let _ = reply.send(function(game));
+        })));
+        response.await.map_err(|_| anyhow!("Game has stopped"))
+    }
+}
+
+fn join(game: &mut Game, address: SocketAddr) -> Result<Joined, DisconnectReason> {
+    let (id, events, private) = game.client_add(address)?;
+    if game.client_joinable(id) {
+        game.player_add(id);
+    }
+
+    Ok(Joined {
+        id,
+        events,
+        private,
+        initial: game.messages_initial(id),
+    })
+}
+
+/// Runs the game in its own task, which owns it and receives commands from
+/// the returned handle. Client inputs are applied at tick boundaries, so a
+/// busy server doesn't delay ticks while waiting for a lock.
+pub fn spawn(game: Game, config: watch::Receiver<Config>) -> GameHandle {
+    let (commands, receiver) = unbounded_channel();
+    tokio::spawn(game_loop(game, receiver, config));
+    GameHandle { commands }
+}
+
+async fn game_loop(
+    mut game: Game,
+    mut commands: UnboundedReceiver<Command>,
+    mut config: watch::Receiver<Config>,
+) {
+    let mut tick = config.borrow().tick;
+    let mut interval = tokio::time::interval(Duration::from_millis(tick));
+    let mut inputs: Vec<(PlayerId, ClientMessage)> = vec![];
+    let mut watching = true;
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                for (id, message) in inputs.drain(..) {
+                    game.handle(id, &message);
+                }
+
+                if !game.paused() {
+                    trace!("Running game tick");
+                    let timer = metrics::TICK_DURATION.start_timer();
+                    game.tick();
+                    timer.observe_duration();
+                    metrics::TICKS.inc();
+                }
+            }
+            command = commands.recv() => match command {
+                Some(Command::Join { address, reply }) => {
+                    let _ = reply.send(join(&mut game, address));
+                }
+                Some(Command::Leave { id, reason }) => {
+                    inputs.retain(|(input, _)| *input != id);
+                    match reason {
+                        Some(reason) => game.client_disconnect(id, reason),
+                        None => game.client_remove(id),
+                    };
+                }
+                Some(Command::Input { id, message }) => inputs.push((id, message)),
+                Some(Command::Call(function)) => function(&mut game),
+                None => break,
+            },
+            changed = config.changed(), if watching => {
+                if changed.is_err() {
+                    watching = false;
+                    continue;
+                }
+
+                // apply live settings
+                let config = config.borrow().clone();
+                if config.tick != tick {
+                    info!("Changing tick from {}ms to {}ms", tick, config.tick);
+                    tick = config.tick;
+                    interval = tokio::time::interval(Duration::from_millis(tick));
+                }
+
+                game.food_set(config.food);
+                game.motd_set(config.motd);
+                game.auth_required_set(config.auth.required);
+                game.spawn_set(config.spawn);
+                game.corpse_set(config.corpse);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xenopeltis_game::{mode, Map};
+
+    #[tokio::test(start_paused = true)]
+    async fn inputs_wait_for_tick() {
+        let config = Config {
+            tick: 1000,
+            ..Config::default()
+        };
+        let (_config, receiver) = watch::channel(config);
+        let map = Map::new(5, 5).unwrap();
+        let game = spawn(Game::new(map, mode::mode("classic").unwrap()), receiver);
+        let joined = game.join(([127, 0, 0, 1], 0).into()).await.unwrap();
+        // let the first tick, which is immediate, pass
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        // a lone player votes for a pause, but only once the next tick comes
+        game.input(joined.id, ClientMessage::Pause);
+        assert!(!game.call(|game| game.paused()).await.unwrap());
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        assert!(game.call(|game| game.paused()).await.unwrap());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn inputs_apply_in_order() {
+        let (_config, receiver) = watch::channel(Config::default());
+        let map = Map::new(5, 5).unwrap();
+        let game = spawn(Game::new(map, mode::mode("classic").unwrap()), receiver);
+        let joined = game.join(([127, 0, 0, 1], 0).into()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // voting twice in one tick takes the vote back
+        game.input(joined.id, ClientMessage::Pause);
+        game.input(joined.id, ClientMessage::Pause);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!game.call(|game| game.paused()).await.unwrap());
+
+        game.input(joined.id, ClientMessage::Pause);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(game.call(|game| game.paused()).await.unwrap());
+    }
+}
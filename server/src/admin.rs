@@ -0,0 +1,186 @@
+use crate::actor::GameHandle;
+use crate::config::Config;
+use anyhow::Result;
+use futures::prelude::*;
+use log::*;
+use std::fs::{DirBuilder, Permissions};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::watch;
+use tokio_serde::{formats::Bincode, Framed};
+use tokio_util::codec::{Framed as FramedCodec, LengthDelimitedCodec};
+use xenopeltis_common::*;
+use xenopeltis_game::Game;
+
+/// Runs an admin command against the game or the configuration.
+pub async fn execute(
+    game: &GameHandle,
+    config: &watch::Sender<Config>,
+    command: AdminCommand,
+) -> AdminResponse {
+    info!("Running admin command {:?}", command);
+    match command {
+        AdminCommand::Config(command) => execute_config(config, command),
+        AdminCommand::Game(command) => game
+            .call(move |game| execute_game(game, command))
+            .await
+            .unwrap_or_else(|e| AdminResponse::Error(e.to_string())),
+    }
+}
+
+/// Runs an admin command that changes the configuration.
+fn execute_config(config: &watch::Sender<Config>, command: ConfigCommand) -> AdminResponse {
+    use ConfigCommand::*;
+    match command {
+        Tick(0) => return AdminResponse::Error("Tick duration must not be zero".into()),
+        Tick(tick) => config.send_modify(|config| config.tick = tick),
+        Food(food) => config.send_modify(|config| config.food = food),
+    }
+
+    AdminResponse::Done
+}
+
+/// Runs an admin command that changes the game itself.
+fn execute_game(game: &mut Game, command: GameCommand) -> AdminResponse {
+    use GameCommand::*;
+    match command {
+        Players => {
+            let players = game
+                .player_stats()
+                .into_iter()
+                .map(|player| PlayerInfo {
+                    id: player.id,
+                    address: game.client_address(player.id),
+                    name: player.name,
+                    length: player.length,
+                })
+                .collect();
+            return AdminResponse::Players(players);
+        }
+        Kick(id) => {
+            if !game.client_disconnect(id, DisconnectReason::Kicked) {
+                return AdminResponse::Error(format!("No player with id {}", id));
+            }
+        }
+        Ban(address) => game.ban(address),
+        Unban(address) => {
+            if !game.unban(address) {
+                return AdminResponse::Error(format!("Address {} is not banned", address));
+            }
+        }
+        BanName(name) => game.ban_name(name),
+        UnbanName(name) => {
+            if !game.unban_name(&name) {
+                return AdminResponse::Error(format!("Name {} is not banned", name));
+            }
+        }
+        Pause => game.pause_set(true),
+        Resume => game.pause_set(false),
+        Reset => game.reset(),
+        Broadcast(text) => game.broadcast(text),
+    }
+
+    AdminResponse::Done
+}
+
+async fn admin_handler(
+    game: GameHandle,
+    config: Arc<watch::Sender<Config>>,
+    stream: UnixStream,
+) -> Result<()> {
+    let framed_stream = FramedCodec::new(stream, LengthDelimitedCodec::new());
+    let mut framed: Framed<_, AdminCommand, AdminResponse, _> = Framed::new(
+        framed_stream,
+        Bincode::<AdminCommand, AdminResponse>::default(),
+    );
+
+    while let Some(command) = framed.try_next().await? {
+        let response = execute(&game, &config, command).await;
+        framed.send(response).await?;
+    }
+
+    Ok(())
+}
+
+/// Binds the admin socket inside a directory only we can enter and moves it
+/// into place once its permissions are restricted, so no other user can
+/// connect in between.
+fn bind(path: &Path) -> Result<UnixListener> {
+    let mut private = path.as_os_str().to_owned();
+    private.push(".tmp");
+    let private = PathBuf::from(private);
+    let temporary = private.join("socket");
+
+    // remove stale socket from previous run
+    let _ = std::fs::remove_file(path);
+    let _ = std::fs::remove_dir_all(&private);
+    DirBuilder::new().mode(0o700).create(&private)?;
+    let result = UnixListener::bind(&temporary).and_then(|listener| {
+        std::fs::set_permissions(&temporary, Permissions::from_mode(0o600))?;
+        std::fs::rename(&temporary, path)?;
+        Ok(listener)
+    });
+    let _ = std::fs::remove_dir_all(&private);
+    Ok(result?)
+}
+
+/// Accepts admin connections on a Unix socket, which only the user running
+/// the server can access.
+pub async fn admin_loop(
+    path: PathBuf,
+    game: GameHandle,
+    config: Arc<watch::Sender<Config>>,
+) -> Result<()> {
+    let listener = bind(&path)?;
+    info!("Listening for admin commands on {}", path.display());
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let game = game.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            if let Err(e) = admin_handler(game, config, stream).await {
+                error!("Error in admin connection: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn bind_restricts_socket() {
+        let directory = std::env::temp_dir();
+        let name = format!("xenopeltis-admin-{}", std::process::id());
+        let path = directory.join(&name);
+        let _listener = bind(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(!directory.join(name + ".tmp").exists());
+        UnixStream::connect(&path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn config_commands() {
+        let (config, _) = watch::channel(Config::default());
+        assert!(matches!(
+            execute_config(&config, ConfigCommand::Tick(0)),
+            AdminResponse::Error(_)
+        ));
+        assert!(matches!(
+            execute_config(&config, ConfigCommand::Tick(50)),
+            AdminResponse::Done
+        ));
+        assert!(matches!(
+            execute_config(&config, ConfigCommand::Food(7)),
+            AdminResponse::Done
+        ));
+        assert_eq!(config.borrow().tick, 50);
+        assert_eq!(config.borrow().food, 7);
+    }
+}
@@ -0,0 +1,273 @@
+use anyhow::{anyhow, Context, Result};
+use log::*;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use xenopeltis_common::name_valid;
+use xenopeltis_game::{Corpse, Spawn};
+
+/// Server configuration, loaded from a TOML file.
+///
+/// Settings marked as live are applied when the server receives a SIGHUP,
+/// all others need a restart.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address to listen on for game connections.
+    pub listen: SocketAddr,
+    /// Accept game connections over TLS only.
+    pub tls: Option<Tls>,
+    /// Address to serve metrics and the status API on over HTTP, disabled
+    /// if not set.
+    pub http: Option<SocketAddr>,
+    /// Rows of the board, unless a map is used.
+    pub rows: usize,
+    /// Columns of the board, unless a map is used.
+    pub cols: usize,
+    /// Amount of food on the board (live).
+    pub food: usize,
+    /// Milliseconds between game ticks (live).
+    pub tick: u64,
+    /// Name of the game mode.
+    pub mode: String,
+    /// Map file to load the board from.
+    pub map: Option<PathBuf>,
+    /// Message of the day, shown to connecting players (live).
+    pub motd: Option<String>,
+    /// SQLite database for the leaderboard and accounts, disabled if not set.
+    pub database: Option<PathBuf>,
+    /// Seconds to warn players before shutting down (live).
+    pub shutdown: u64,
+    pub limits: Limits,
+    pub bots: Bots,
+    /// How new snakes are put on the board (live).
+    pub spawn: Spawn,
+    /// What dead snakes leave behind (live).
+    pub corpse: Corpse,
+    pub admin: Admin,
+    pub auth: Auth,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Tls {
+    /// PEM file with the certificate chain.
+    pub certificate: PathBuf,
+    /// PEM file with the private key.
+    pub key: PathBuf,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Maximum amount of connected players.
+    pub max_players: usize,
+    /// Maximum amount of connections from a single address.
+    pub max_per_address: usize,
+    /// Messages a client may send per second, also the allowed burst (live,
+    /// for new connections).
+    pub messages_per_second: u32,
+    /// Largest message a client may send, in bytes (live, for new
+    /// connections).
+    pub message_size: usize,
+    /// Proxies that pass on the addresses of their clients, which count
+    /// towards the limits and bans instead of the proxy (live, for new
+    /// connections).
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bots {
+    /// Amount of computer-controlled snakes.
+    pub count: usize,
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Admin {
+    /// Unix socket for the admin console, disabled if not set.
+    pub socket: Option<PathBuf>,
+    /// Password for remote administration, disabled if not set (live).
+    pub password: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Auth {
+    /// Only let players join after logging in (live).
+    pub required: bool,
+    /// Pre-shared tokens for bots, by account name (live).
+    pub tokens: BTreeMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen: "0.0.0.0:8000".parse().unwrap(),
+            tls: None,
+            http: None,
+            rows: 20,
+            cols: 80,
+            food: 2,
+            tick: 100,
+            mode: "classic".into(),
+            map: None,
+            motd: None,
+            database: None,
+            shutdown: 5,
+            limits: Limits::default(),
+            bots: Bots::default(),
+            spawn: Spawn::default(),
+            corpse: Corpse::default(),
+            admin: Admin::default(),
+            auth: Auth::default(),
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_players: 64,
+            max_per_address: 4,
+            messages_per_second: 50,
+            message_size: 4096,
+            trusted_proxies: vec![Ipv4Addr::LOCALHOST.into(), Ipv6Addr::LOCALHOST.into()],
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Reading config {}", path.display()))?;
+        toml::from_str(&data).with_context(|| format!("Parsing config {}", path.display()))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.map.is_none() && (self.rows < 3 || self.cols < 3) {
+            return Err(anyhow!("Board must be at least 3x3"));
+        }
+
+        if self.tick == 0 {
+            return Err(anyhow!("Tick duration must not be zero"));
+        }
+
+        if self.spawn.length == 0 {
+            return Err(anyhow!("Initial snake length must not be zero"));
+        }
+
+        if self.spawn.runway == 0 {
+            return Err(anyhow!("Spawn runway must not be zero"));
+        }
+
+        if self.corpse.percent > 100 {
+            return Err(anyhow!("Corpse food percentage must be at most 100"));
+        }
+
+        if self.limits.messages_per_second == 0 {
+            return Err(anyhow!("Message rate limit must not be zero"));
+        }
+
+        if self.admin.password.as_deref() == Some("") {
+            return Err(anyhow!("Admin password must not be empty"));
+        }
+
+        for (name, token) in &self.auth.tokens {
+            if !name_valid(name) {
+                return Err(anyhow!("Invalid account name for token: {:?}", name));
+            }
+            if token.is_empty() {
+                return Err(anyhow!("Token for {} must not be empty", name));
+            }
+        }
+
+        if self.auth.required && self.database.is_none() && self.auth.tokens.is_empty() {
+            return Err(anyhow!(
+                "Authentication is required, but neither database nor tokens are set"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Warns about settings that changed, but only take effect after a restart.
+    pub fn warn_restart(&self, new: &Config) {
+        let changed = [
+            ("listen", self.listen != new.listen),
+            ("tls", self.tls != new.tls),
+            ("http", self.http != new.http),
+            ("rows", self.rows != new.rows),
+            ("cols", self.cols != new.cols),
+            ("mode", self.mode != new.mode),
+            ("map", self.map != new.map),
+            ("database", self.database != new.database),
+            (
+                "limits.max_players",
+                self.limits.max_players != new.limits.max_players,
+            ),
+            (
+                "limits.max_per_address",
+                self.limits.max_per_address != new.limits.max_per_address,
+            ),
+            ("bots", self.bots != new.bots),
+            ("admin.socket", self.admin.socket != new.admin.socket),
+        ];
+
+        for (name, _) in changed.iter().filter(|(_, changed)| *changed) {
+            warn!("Setting {} changed, needs restart to take effect", name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(data: &str) -> Result<Config> {
+        let config: Config = toml::from_str(data)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    #[test]
+    fn example_has_defaults() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("server.example.toml");
+        assert_eq!(Config::load(&path).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn parse_partial() {
+        let config = parse("rows = 30\n[limits]\nmax_players = 8\n").unwrap();
+        assert_eq!(config.rows, 30);
+        assert_eq!(config.cols, Config::default().cols);
+        assert_eq!(config.limits.max_players, 8);
+        assert_eq!(config.limits.max_per_address, 4);
+    }
+
+    #[test]
+    fn parse_unknown_fields() {
+        assert!(parse("colums = 30\n").is_err());
+        assert!(parse("[limits]\nplayers = 8\n").is_err());
+    }
+
+    #[test]
+    fn validate() {
+        Config::default().validate().unwrap();
+        assert!(parse("rows = 2\n").is_err());
+        // a map brings its own size
+        parse("rows = 2\nmap = \"arena.map\"\n").unwrap();
+        assert!(parse("tick = 0\n").is_err());
+        assert!(parse("[spawn]\nlength = 0\n").is_err());
+        assert!(parse("[spawn]\nrunway = 0\n").is_err());
+        assert!(parse("[corpse]\npercent = 101\n").is_err());
+        assert!(parse("[limits]\nmessages_per_second = 0\n").is_err());
+        assert!(parse("[admin]\npassword = \"\"\n").is_err());
+        assert!(parse("[auth.tokens]\n\"not a name\" = \"secret\"\n").is_err());
+        assert!(parse("[auth.tokens]\nbot = \"\"\n").is_err());
+        assert!(parse("[auth]\nrequired = true\n").is_err());
+        parse("[auth]\nrequired = true\n[auth.tokens]\nbot = \"secret\"\n").unwrap();
+    }
+}
@@ -0,0 +1,23 @@
+use anyhow::Result;
+use log::*;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Opens the SQLite database shared by the leaderboard and the accounts.
+pub async fn open(path: &Path) -> Result<SqlitePool> {
+    let options = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true);
+    let pool = SqlitePool::connect_with(options).await?;
+    info!("Opened database {}", path.display());
+    Ok(pool)
+}
+
+/// Current time in seconds since the epoch, as stored in the database.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
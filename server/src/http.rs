@@ -0,0 +1,161 @@
+use crate::actor::GameHandle;
+use crate::config::Config;
+use crate::metrics;
+use anyhow::Result;
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::*;
+use serde::Serialize;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::watch;
+use xenopeltis_common::*;
+use xenopeltis_game::{PlayerStats, ROOM};
+
+#[derive(Serialize)]
+struct Status {
+    version: &'static str,
+    /// Seconds since the server started.
+    uptime: u64,
+    players: usize,
+    rooms: Vec<RoomSummary>,
+}
+
+#[derive(Serialize)]
+struct RoomSummary {
+    id: &'static str,
+    players: usize,
+    snakes: usize,
+    paused: bool,
+}
+
+#[derive(Serialize)]
+struct Room {
+    id: &'static str,
+    mode: &'static str,
+    rows: usize,
+    cols: usize,
+    tick: u64,
+    food: usize,
+    pause: Option<PauseReason>,
+    players: Vec<PlayerStats>,
+}
+
+#[derive(Serialize)]
+struct Board {
+    id: &'static str,
+    fields: Vec<Vec<Field>>,
+}
+
+/// Shared state of the HTTP handlers.
+struct Context {
+    game: GameHandle,
+    config: Arc<watch::Sender<Config>>,
+    started: Instant,
+}
+
+fn json<T: Serialize>(value: &T) -> Result<Response<Body>> {
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::to_string(value)?.into())?)
+}
+
+fn not_found() -> Result<Response<Body>> {
+    Ok(Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::empty())?)
+}
+
+async fn status(context: &Context) -> Result<Response<Body>> {
+    let stats = context.game.call(|game| game.stats()).await?;
+    json(&Status {
+        version: env!("CARGO_PKG_VERSION"),
+        uptime: context.started.elapsed().as_secs(),
+        players: stats.players,
+        rooms: vec![RoomSummary {
+            id: ROOM,
+            players: stats.players,
+            snakes: stats.snakes,
+            paused: stats.paused,
+        }],
+    })
+}
+
+async fn room(context: &Context) -> Result<Response<Body>> {
+    let tick = context.config.borrow().tick;
+    let room = context
+        .game
+        .call(move |game| {
+            let board = game.board();
+            Room {
+                id: ROOM,
+                mode: game.mode_name(),
+                rows: board.len(),
+                cols: board.first().map(|row| row.len()).unwrap_or(0),
+                tick,
+                food: game.stats().food,
+                pause: game.pause_reason(),
+                players: game.player_stats(),
+            }
+        })
+        .await?;
+    json(&room)
+}
+
+async fn board(context: &Context) -> Result<Response<Body>> {
+    let fields = context.game.call(|game| game.board().clone()).await?;
+    json(&Board { id: ROOM, fields })
+}
+
+async fn handle(context: Arc<Context>, request: Request<Body>) -> Result<Response<Body>> {
+    if request.method() != Method::GET {
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .body(Body::empty())?);
+    }
+
+    let segments: Vec<_> = request
+        .uri()
+        .path()
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    match segments.as_slice() {
+        ["metrics"] => {
+            let stats = context.game.call(|game| game.stats()).await?;
+            let body = metrics::render(&stats);
+            Ok(Response::builder()
+                .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+                .body(body.into())?)
+        }
+        ["status"] => status(&context).await,
+        ["rooms", ROOM] => room(&context).await,
+        ["rooms", ROOM, "board"] => board(&context).await,
+        _ => not_found(),
+    }
+}
+
+/// Serves metrics and a read-only JSON API over HTTP.
+pub async fn http_loop(
+    address: SocketAddr,
+    game: GameHandle,
+    config: Arc<watch::Sender<Config>>,
+) -> Result<()> {
+    let context = Arc::new(Context {
+        game,
+        config,
+        started: Instant::now(),
+    });
+    let service = make_service_fn(move |_| {
+        let context = context.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| handle(context.clone(), request))) }
+    });
+
+    let server = Server::try_bind(&address)?.serve(service);
+    info!("Serving HTTP on {}", address);
+    server.await?;
+    Ok(())
+}
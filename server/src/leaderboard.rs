@@ -0,0 +1,176 @@
+use crate::database::now;
+use anyhow::Result;
+use log::*;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use tokio::sync::mpsc::UnboundedReceiver;
+use xenopeltis_common::*;
+use xenopeltis_game::GameResult;
+
+/// How many players are shown on the leaderboard.
+const LEADERBOARD_SIZE: i64 = 10;
+
+const WEEK_SECONDS: i64 = 7 * 24 * 60 * 60;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS games (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    length INTEGER NOT NULL,
+    kills INTEGER NOT NULL,
+    won BOOLEAN NOT NULL,
+    finished INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS games_name ON games (name);
+CREATE INDEX IF NOT EXISTS games_finished ON games (finished);
+";
+
+/// Finished games, stored in a SQLite database.
+#[derive(Clone, Debug)]
+pub struct Leaderboard {
+    pool: SqlitePool,
+}
+
+impl Leaderboard {
+    pub async fn new(pool: SqlitePool) -> Result<Self> {
+        sqlx::query(SCHEMA).execute(&pool).await?;
+        Ok(Leaderboard { pool })
+    }
+
+    pub async fn record(&self, result: &GameResult) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO games (name, length, kills, won, finished) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&result.name)
+        .bind(result.length as i64)
+        .bind(result.kills as i64)
+        .bind(result.won)
+        .bind(now())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn query(&self, period: LeaderboardPeriod) -> Result<Vec<LeaderboardEntry>> {
+        let since = match period {
+            LeaderboardPeriod::AllTime => 0,
+            LeaderboardPeriod::Weekly => now() - WEEK_SECONDS,
+        };
+
+        let rows = sqlx::query(
+            "SELECT name, MAX(length), SUM(kills), SUM(won), COUNT(*) FROM games
+            WHERE finished >= ? GROUP BY name
+            ORDER BY MAX(length) DESC, SUM(won) DESC, SUM(kills) DESC LIMIT ?",
+        )
+        .bind(since)
+        .bind(LEADERBOARD_SIZE)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let entries = rows
+            .iter()
+            .map(|row| LeaderboardEntry {
+                name: row.get(0),
+                best_length: row.get::<i64, _>(1) as usize,
+                kills: row.get::<i64, _>(2) as usize,
+                wins: row.get::<i64, _>(3) as usize,
+                games: row.get::<i64, _>(4) as usize,
+            })
+            .collect();
+        Ok(entries)
+    }
+}
+
+/// Stores game results as they come in.
+pub async fn leaderboard_loop(
+    leaderboard: Leaderboard,
+    mut results: UnboundedReceiver<GameResult>,
+) {
+    while let Some(result) = results.recv().await {
+        info!(
+            "Recording game of {} with length {}",
+            result.name, result.length
+        );
+        if let Err(e) = leaderboard.record(&result).await {
+            error!("Error recording game of {}: {}", result.name, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use std::path::PathBuf;
+
+    async fn leaderboard(name: &str) -> (Leaderboard, PathBuf) {
+        let path = std::env::temp_dir().join(format!(
+            "xenopeltis-leaderboard-{}-{}.db",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_file(&path);
+        let pool = database::open(&path).await.unwrap();
+        (Leaderboard::new(pool).await.unwrap(), path)
+    }
+
+    fn result(name: &str, length: usize, kills: usize, won: bool) -> GameResult {
+        GameResult {
+            name: name.into(),
+            length,
+            kills,
+            won,
+        }
+    }
+
+    #[tokio::test]
+    async fn query_ranks_by_best_length() {
+        let (leaderboard, path) = leaderboard("rank").await;
+        leaderboard
+            .record(&result("alice", 10, 1, false))
+            .await
+            .unwrap();
+        leaderboard
+            .record(&result("alice", 5, 0, true))
+            .await
+            .unwrap();
+        leaderboard
+            .record(&result("bob", 7, 3, false))
+            .await
+            .unwrap();
+
+        let entries = leaderboard.query(LeaderboardPeriod::AllTime).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let names: Vec<_> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["alice", "bob"]);
+        assert_eq!(entries[0].best_length, 10);
+        assert_eq!(entries[0].kills, 1);
+        assert_eq!(entries[0].wins, 1);
+        assert_eq!(entries[0].games, 2);
+        assert_eq!(entries[1].kills, 3);
+    }
+
+    #[tokio::test]
+    async fn weekly_leaves_out_older_games() {
+        let (leaderboard, path) = leaderboard("weekly").await;
+        leaderboard
+            .record(&result("alice", 3, 0, false))
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO games (name, length, kills, won, finished) VALUES ('bob', 9, 0, 0, ?)",
+        )
+        .bind(now() - WEEK_SECONDS - 60)
+        .execute(&leaderboard.pool)
+        .await
+        .unwrap();
+
+        let all_time = leaderboard.query(LeaderboardPeriod::AllTime).await.unwrap();
+        let weekly = leaderboard.query(LeaderboardPeriod::Weekly).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(all_time.len(), 2);
+        assert_eq!(all_time[0].name, "bob");
+        assert_eq!(weekly.len(), 1);
+        assert_eq!(weekly[0].name, "alice");
+    }
+}
@@ -0,0 +1,78 @@
+use std::time::Instant;
+
+/// Token bucket limiting how many messages a client sends. Allows bursts of
+/// up to `rate` messages, refilling at `rate` messages per second.
+#[derive(Clone, Debug)]
+pub struct RateLimit {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimit {
+    pub fn new(rate: u32) -> Self {
+        RateLimit {
+            rate: rate.into(),
+            tokens: rate.into(),
+            last: Instant::now(),
+        }
+    }
+
+    /// Takes a token for a message, returns false if the client is over
+    /// the limit.
+    pub fn check(&mut self) -> bool {
+        self.check_at(Instant::now())
+    }
+
+    fn check_at(&mut self, now: Instant) -> bool {
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.last = now;
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn allows_burst_up_to_rate() {
+        let mut limit = RateLimit::new(3);
+        let now = limit.last;
+        assert!(limit.check_at(now));
+        assert!(limit.check_at(now));
+        assert!(limit.check_at(now));
+        assert!(!limit.check_at(now));
+    }
+
+    #[test]
+    fn refills_over_time() {
+        let mut limit = RateLimit::new(2);
+        let now = limit.last;
+        assert!(limit.check_at(now));
+        assert!(limit.check_at(now));
+        assert!(!limit.check_at(now));
+
+        // one token comes back every half second
+        let later = now + Duration::from_millis(500);
+        assert!(limit.check_at(later));
+        assert!(!limit.check_at(later));
+    }
+
+    #[test]
+    fn refill_is_capped_at_rate() {
+        let mut limit = RateLimit::new(2);
+        let later = limit.last + Duration::from_secs(60);
+        assert!(limit.check_at(later));
+        assert!(limit.check_at(later));
+        assert!(!limit.check_at(later));
+    }
+}
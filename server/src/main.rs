@@ -1,109 +1,517 @@
-mod game;
+mod accounts;
+mod actor;
+mod admin;
+mod config;
+mod database;
+mod http;
+mod leaderboard;
+mod limit;
+mod metrics;
+mod tls;
 
-use anyhow::Result;
+use accounts::Accounts;
+use actor::{GameHandle, Joined};
+use anyhow::{anyhow, Result};
+use config::{Config, Tls};
 use futures::prelude::*;
-use game::Game;
+
+use leaderboard::Leaderboard;
+use limit::RateLimit;
 use log::*;
+
+use metrics::CountingWriter;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 use structopt::StructOpt;
-use tokio::io::AsyncWriteExt;
-use tokio::net::{tcp::OwnedWriteHalf, TcpSocket, TcpStream};
-use tokio::sync::broadcast::{channel, Receiver};
-use tokio::sync::Mutex;
+use subtle::ConstantTimeEq;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpSocket;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use tokio::sync::mpsc::{self, unbounded_channel, UnboundedReceiver};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
 use tokio_serde::{formats::SymmetricalBincode, SymmetricallyFramed};
-use tokio_util::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
+use tokio_util::codec::{FramedRead, FramedWrite, LengthDelimitedCodec, LengthDelimitedCodecError};
 use xenopeltis_common::*;
+use xenopeltis_game::{mode, Game, Map};
 
 #[derive(StructOpt)]
 struct Options {
-    #[structopt(long, short, default_value = "0.0.0.0:8000")]
-    listen: SocketAddr,
-    #[structopt(long, short, default_value = "20")]
-    rows: usize,
-    #[structopt(long, short, default_value = "80")]
-    cols: usize,
-    #[structopt(long, short, default_value = "2")]
-    food: usize,
-    #[structopt(long, short, default_value = "100")]
-    tick: u64,
+    #[structopt(long, env = "XENOPELTIS_CONFIG")]
+    config: Option<PathBuf>,
+    #[structopt(long, short, env = "XENOPELTIS_LISTEN")]
+    listen: Option<SocketAddr>,
+    #[structopt(long, env = "XENOPELTIS_TLS_CERTIFICATE", requires = "tls-key")]
+    tls_certificate: Option<PathBuf>,
+    #[structopt(long, env = "XENOPELTIS_TLS_KEY", requires = "tls-certificate")]
+    tls_key: Option<PathBuf>,
+    #[structopt(long, env = "XENOPELTIS_HTTP")]
+    http: Option<SocketAddr>,
+    #[structopt(long, short, env = "XENOPELTIS_ROWS")]
+    rows: Option<usize>,
+    #[structopt(long, short, env = "XENOPELTIS_COLS")]
+    cols: Option<usize>,
+    #[structopt(long, short, env = "XENOPELTIS_FOOD")]
+    food: Option<usize>,
+    #[structopt(long, short, env = "XENOPELTIS_TICK")]
+    tick: Option<u64>,
+    #[structopt(long, short, env = "XENOPELTIS_MODE")]
+    mode: Option<String>,
+    #[structopt(long, env = "XENOPELTIS_MAP")]
+    map: Option<PathBuf>,
+    #[structopt(long, env = "XENOPELTIS_MOTD")]
+    motd: Option<String>,
+    #[structopt(long, env = "XENOPELTIS_DATABASE")]
+    database: Option<PathBuf>,
+    #[structopt(long, env = "XENOPELTIS_MAX_PLAYERS")]
+    max_players: Option<usize>,
+    #[structopt(long, env = "XENOPELTIS_BOTS")]
+    bots: Option<usize>,
+    #[structopt(long, env = "XENOPELTIS_ADMIN_SOCKET")]
+    admin_socket: Option<PathBuf>,
+    #[structopt(long, env = "XENOPELTIS_ADMIN_PASSWORD", hide_env_values = true)]
+    admin_password: Option<String>,
 }
 
-async fn handler_write(
-    game: Arc<Mutex<Game>>,
-    writer: OwnedWriteHalf,
-    peer: SocketAddr,
+impl Options {
+    /// Loads the configuration file, if any, and applies the command line
+    /// options and environment variables on top of it.
+    fn config(&self) -> Result<Config> {
+        let mut config = match &self.config {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+
+        if let Some(listen) = self.listen {
+            config.listen = listen;
+        }
+        if let (Some(certificate), Some(key)) = (&self.tls_certificate, &self.tls_key) {
+            config.tls = Some(Tls {
+                certificate: certificate.clone(),
+                key: key.clone(),
+            });
+        }
+        if let Some(http) = self.http {
+            config.http = Some(http);
+        }
+        if let Some(rows) = self.rows {
+            config.rows = rows;
+        }
+        if let Some(cols) = self.cols {
+            config.cols = cols;
+        }
+        if let Some(food) = self.food {
+            config.food = food;
+        }
+        if let Some(tick) = self.tick {
+            config.tick = tick;
+        }
+        if let Some(mode) = &self.mode {
+            config.mode = mode.clone();
+        }
+        if let Some(map) = &self.map {
+            config.map = Some(map.clone());
+        }
+        if let Some(motd) = &self.motd {
+            config.motd = Some(motd.clone());
+        }
+        if let Some(database) = &self.database {
+            config.database = Some(database.clone());
+        }
+        if let Some(max_players) = self.max_players {
+            config.limits.max_players = max_players;
+        }
+        if let Some(bots) = self.bots {
+            config.bots.count = bots;
+        }
+        if let Some(socket) = &self.admin_socket {
+            config.admin.socket = Some(socket.clone());
+        }
+        if let Some(password) = &self.admin_password {
+            config.admin.password = Some(password.clone());
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+async fn handler_write<W: AsyncWrite + Unpin>(
+    writer: W,
+    id: PlayerId,
+    initial: Vec<ServerMessage>,
     mut events: Receiver<ServerMessage>,
-) {
-    let framed_writer = FramedWrite::new(writer, LengthDelimitedCodec::new());
+    mut private: UnboundedReceiver<ServerMessage>,
+) -> Result<()> {
+    let framed_writer = FramedWrite::new(CountingWriter::new(writer), LengthDelimitedCodec::new());
     let mut framed = SymmetricallyFramed::new(
         framed_writer,
         SymmetricalBincode::<ServerMessage>::default(),
-    );
+    )
+    .with(|message: ServerMessage| {
+        metrics::MESSAGES_SENT
+            .with_label_values(&[metrics::server_message_type(&message)])
+            .inc();
+        future::ready(Ok::<_, std::io::Error>(message))
+    });
 
-    let game_lock = game.lock().await;
-    let messages = game_lock.messages_initial(peer);
-    drop(game_lock);
-
-    for message in messages {
-        framed.send(message).await;
+    for message in initial {
+        framed.send(message).await?;
     }
 
     loop {
-        match events.recv().await {
-            Ok(event) => {
-                framed.send(event).await;
-            }
-            _ => {}
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => framed.send(event).await?,
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Player {} missed {} events", id, missed);
+                    metrics::BROADCAST_LAGGED.inc();
+                }
+                Err(RecvError::Closed) => {}
+            },
+            message = private.recv() => match message {
+                Some(message) => framed.send(message).await?,
+                None => break,
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// Compares secrets in constant time, so their contents can't be guessed
+/// from how long the comparison takes.
+fn secret_eq(secret: &str, guess: &str) -> bool {
+    secret.as_bytes().ct_eq(guess.as_bytes()).into()
+}
+
+/// Runs an admin command sent by a client, if it knows the password. A wrong
+/// password disconnects the client, so it can't be guessed quickly.
+async fn handle_admin(
+    game: &GameHandle,
+    config: &watch::Sender<Config>,
+    id: PlayerId,
+    message: AdminMessage,
+) -> Option<DisconnectReason> {
+    let password = config.borrow().admin.password.clone();
+    match password {
+        Some(password) if secret_eq(&password, &message.password) => {
+            let response = admin::execute(game, config, message.command).await;
+            game.notify(id, ServerMessage::Admin(response));
+            None
+        }
+        _ => {
+            warn!("Player {} tried admin command with wrong password", id);
+            Some(DisconnectReason::NotAuthorized)
         }
     }
 }
 
-async fn handler(game: Arc<Mutex<Game>>, mut connection: TcpStream, peer: SocketAddr) {
-    info!("Connection from {}", peer);
-    let mut game_lock = game.lock().await;
-    let events = game_lock.player_add(peer);
-    drop(game_lock);
+/// Sends the leaderboard to a client.
+async fn handle_leaderboard(
+    game: &GameHandle,
+    leaderboard: &Option<Leaderboard>,
+    id: PlayerId,
+    period: LeaderboardPeriod,
+) {
+    let entries = match leaderboard {
+        Some(leaderboard) => leaderboard.query(period).await,
+        None => Err(anyhow!("Leaderboard is disabled")),
+    };
 
-    let (reader, writer) = connection.into_split();
-    tokio::spawn(handler_write(game.clone(), writer, peer, events));
+    match entries {
+        Ok(entries) => game.notify(
+            id,
+            ServerMessage::Leaderboard(LeaderboardMessage { period, entries }),
+        ),
+        Err(e) => {
+            warn!("Can't send leaderboard to player {}: {}", id, e);
+            let message = e.to_string();
+            let _ = game
+                .call(move |game| game.error(id, ErrorCode::Unavailable, &message))
+                .await;
+        }
+    }
+}
+
+/// Checks the credentials of a client, returning the account name.
+async fn authenticate(
+    config: &watch::Sender<Config>,
+    accounts: &Option<Accounts>,
+    message: ClientMessage,
+) -> Result<String> {
+    match (message, accounts) {
+        (ClientMessage::Register(credentials), Some(accounts)) => {
+            accounts
+                .register(&credentials.name, &credentials.password)
+                .await?;
+            info!("Registered account {}", credentials.name);
+            Ok(credentials.name)
+        }
+        (ClientMessage::Login(credentials), Some(accounts)) => {
+            accounts
+                .login(&credentials.name, &credentials.password)
+                .await?;
+            Ok(credentials.name)
+        }
+        (ClientMessage::Register(_), None) | (ClientMessage::Login(_), None) => {
+            Err(anyhow!("Accounts are disabled on this server"))
+        }
+        (ClientMessage::Token(token), _) => config
+            .borrow()
+            .auth
+            .tokens
+            .iter()
+            .find(|(_, expected)| secret_eq(expected, &token.token))
+            .map(|(name, _)| name.clone())
+            .ok_or_else(|| anyhow!("Invalid token")),
+        _ => Err(anyhow!("Not an authentication message")),
+    }
+}
 
-    let framed_reader = FramedRead::new(reader, LengthDelimitedCodec::new());
+/// Logs a client in to an account. A wrong token disconnects the client, so
+/// it can't be guessed quickly, passwords are slow to check anyway.
+async fn handle_auth(
+    game: &GameHandle,
+    config: &watch::Sender<Config>,
+    accounts: &Option<Accounts>,
+    id: PlayerId,
+    message: ClientMessage,
+) -> Option<DisconnectReason> {
+    let token = matches!(message, ClientMessage::Token(_));
+    let result = match authenticate(config, accounts, message).await {
+        Ok(name) => game
+            .call(move |game| game.client_login(id, name.clone()).map(|_| name))
+            .await
+            .and_then(|result| result),
+        Err(e) => Err(e),
+    };
+    let response = match result {
+        Ok(name) => {
+            info!("Player {} logged in as {}", id, name);
+            AuthMessage::LoggedIn(name)
+        }
+        Err(e) if token => {
+            warn!("Player {} failed to log in: {}", id, e);
+            return Some(DisconnectReason::NotAuthorized);
+        }
+        Err(e) => {
+            warn!("Player {} failed to log in: {}", id, e);
+            AuthMessage::Failed(e.to_string())
+        }
+    };
+    game.notify(id, ServerMessage::Auth(response));
+    None
+}
+
+/// Tells a client that could not be added why, before closing the connection.
+async fn reject<S: AsyncWrite + Unpin>(connection: S, reason: DisconnectReason) -> Result<()> {
+    let framed_writer = FramedWrite::new(connection, LengthDelimitedCodec::new());
+    let mut framed = SymmetricallyFramed::new(
+        framed_writer,
+        SymmetricalBincode::<ServerMessage>::default(),
+    );
+    framed
+        .send(ServerMessage::Disconnect(DisconnectMessage { reason }))
+        .await?;
+    Ok(())
+}
+
+/// Handles a client connection, either plain TCP or TLS.
+async fn handler<S: AsyncRead + AsyncWrite + Send + Unpin + 'static>(
+    game: GameHandle,
+    config: Arc<watch::Sender<Config>>,
+    leaderboard: Option<Leaderboard>,
+    accounts: Option<Accounts>,
+    connection: S,
+    mut peer: SocketAddr,
+) {
+    info!("Connection from {}", peer);
+    let (reader, writer) = tokio::io::split(connection);
+    let limits = config.borrow().limits.clone();
+    let codec = LengthDelimitedCodec::builder()
+        .max_frame_length(limits.message_size)
+        .new_codec();
+    let framed_reader = FramedRead::new(reader, codec);
     let mut framed = SymmetricallyFramed::new(
         framed_reader,
         SymmetricalBincode::<ClientMessage>::default(),
     );
+    let mut rate_limit = RateLimit::new(limits.messages_per_second);
 
-    loop {
+    // a trusted proxy first tells us who it connects for, clients on the
+    // same host just start talking
+    let mut first = None;
+    if limits.trusted_proxies.contains(&peer.ip()) {
         match framed.try_next().await {
-            // we got a valid message, handle it
+            Ok(Some(ClientMessage::Forwarded(forwarded))) => {
+                info!("Connection {} is from {}", peer, forwarded.address);
+                peer = forwarded.address;
+            }
+            Ok(Some(message)) => first = Some(message),
+            Ok(None) => return,
+            Err(e) => {
+                error!("Error from {}: {}", peer, e);
+                return;
+            }
+        }
+    }
+
+    let Joined {
+        id,
+        events,
+        private,
+        initial,
+    } = match game.join(peer).await {
+        Ok(joined) => joined,
+        Err(e) => {
+            warn!("Rejecting {}: {}", peer, e);
+            let _ = reject(writer, e).await;
+            return;
+        }
+    };
+    info!("Client {} is player {}", peer, id);
+
+    let mut writer = tokio::spawn(handler_write(writer, id, initial, events, private));
+
+    let mut writer_done = false;
+    let reason = loop {
+        let message = match first.take() {
+            Some(message) => Ok(Some(message)),
+            None => tokio::select! {
+                message = framed.try_next() => message,
+                // writer stops when the client was kicked
+                _ = &mut writer => {
+                    writer_done = true;
+                    break None;
+                }
+            },
+        };
+
+        if let Ok(Some(message)) = &message {
+            metrics::MESSAGES_RECEIVED
+                .with_label_values(&[metrics::client_message_type(message)])
+                .inc();
+        }
+
+        if matches!(message, Ok(Some(_))) && !rate_limit.check() {
+            warn!("Player {} sends too many messages", id);
+            break Some(DisconnectReason::TooManyMessages);
+        }
+
+        match message {
+            // admin commands need the config, handle them here
+            Ok(Some(ClientMessage::Admin(message))) => {
+                if let Some(reason) = handle_admin(&game, &config, id, message).await {
+                    break Some(reason);
+                }
+            }
+            // leaderboard queries the database, don't block the game for it
+            Ok(Some(ClientMessage::Leaderboard(period))) => {
+                handle_leaderboard(&game, &leaderboard, id, period).await;
+            }
+            // authentication checks passwords, don't block the game for it
+            Ok(Some(
+                message @ (ClientMessage::Register(_)
+                | ClientMessage::Login(_)
+                | ClientMessage::Token(_)),
+            )) => {
+                if let Some(reason) = handle_auth(&game, &config, &accounts, id, message).await {
+                    break Some(reason);
+                }
+            }
+            // we got a valid message, the game handles it on the next tick
             Ok(Some(message)) => {
-                info!("Message from {}: {:?}", peer, message);
-                let mut game_lock = game.lock().await;
-                game_lock.handle(peer, &message).await;
+                debug!("Message from {}: {:?}", peer, message);
+                game.input(id, message);
             }
             // end of stream (client closed connection)
-            Ok(None) => break,
+            Ok(None) => break None,
             // some kind of error happened, log it
             Err(e) => {
                 error!("Error from {}: {}", peer, e);
-                break;
+                let too_large = e
+                    .get_ref()
+                    .map(|e| e.is::<LengthDelimitedCodecError>())
+                    .unwrap_or(false);
+                if too_large {
+                    break Some(DisconnectReason::MessageTooLarge);
+                }
+
+                let message = e.to_string();
+                let _ = game
+                    .call(move |game| game.error(id, ErrorCode::InvalidMessage, &message))
+                    .await;
+                break Some(DisconnectReason::InvalidMessage);
             }
         }
+    };
+
+    game.leave(id, reason);
+
+    // let the writer send the remaining messages, like the reason
+    if !writer_done {
+        let _ = writer.await;
     }
+}
 
-    let mut game_lock = game.lock().await;
-    game_lock.player_remove(&peer);
+/// Reloads the configuration when receiving a SIGHUP.
+async fn reload_loop(options: Options, config: Arc<watch::Sender<Config>>) -> Result<()> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    while hangup.recv().await.is_some() {
+        info!("Reloading configuration");
+        match options.config() {
+            Ok(new) => {
+                config.borrow().warn_restart(&new);
+                config.send_replace(new);
+            }
+            Err(e) => error!("Error reloading configuration: {:#}", e),
+        }
+    }
+
+    Ok(())
 }
 
-async fn game_loop(game: Arc<Mutex<Game>>, duration: Duration) {
-    let mut interval = tokio::time::interval(duration);
-    loop {
-        interval.tick().await;
-        info!("Running game tick");
-        let mut game_lock = game.lock().await;
-        game_lock.tick();
+/// How long to wait for connections and the leaderboard after disconnecting
+/// everyone on shutdown.
+const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(5);
+
+/// Counts down while warning players, then disconnects everyone and waits
+/// for the connections and the leaderboard to finish. Another SIGINT skips
+/// the countdown.
+async fn shutdown(
+    game: &GameHandle,
+    countdown: u64,
+    mut connections: mpsc::Receiver<()>,
+    leaderboard: Option<JoinHandle<()>>,
+) {
+    info!("Shutting down in {} seconds", countdown);
+    for seconds in (1..=countdown).rev() {
+        let _ = game.call(move |game| game.shutdown_announce(seconds)).await;
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+            _ = tokio::signal::ctrl_c() => {
+                info!("Skipping shutdown countdown");
+                break;
+            }
+        }
+    }
+
+    let _ = game.call(|game| game.shutdown()).await;
+
+    let finished = tokio::time::timeout(SHUTDOWN_DEADLINE, async {
+        // every connection holds a sender, recv returns once all are closed
+        connections.recv().await;
+        if let Some(leaderboard) = leaderboard {
+            let _ = leaderboard.await;
+        }
+    })
+    .await;
+    if finished.is_err() {
+        warn!("Connections or leaderboard did not finish in time");
     }
 }
 
@@ -111,23 +519,282 @@ async fn game_loop(game: Arc<Mutex<Game>>, duration: Duration) {
 async fn main() -> Result<()> {
     env_logger::init();
     let options = Options::from_args();
+    let config = options.config()?;
 
     let socket = TcpSocket::new_v4()?;
-    socket.bind(options.listen)?;
+    socket.bind(config.listen)?;
 
     let listener = socket.listen(1024)?;
 
-    let mut game = Game::new(options.rows, options.cols);
-    game.food_set(options.food);
+    let mode = mode::mode(&config.mode)?;
+    info!("Running game mode {}", mode.name());
+
+    let map = match &config.map {
+        Some(path) => Map::load(path)?,
+        None => Map::new(config.rows, config.cols)?,
+    };
+
+    let acceptor = match &config.tls {
+        Some(tls) => Some(tls::acceptor(tls)?),
+        None => None,
+    };
+
+    let (leaderboard, accounts) = match &config.database {
+        Some(path) => {
+            let pool = database::open(path).await?;
+            (
+                Some(Leaderboard::new(pool.clone()).await?),
+                Some(Accounts::new(pool).await?),
+            )
+        }
+        None => (None, None),
+    };
 
-    let game = Arc::new(Mutex::new(game));
+    let mut game = Game::new(map, mode);
+    let leaderboard_task = leaderboard.as_ref().map(|leaderboard| {
+        let (results, results_receiver) = unbounded_channel();
+        game.results_set(results);
+        tokio::spawn(leaderboard::leaderboard_loop(
+            leaderboard.clone(),
+            results_receiver,
+        ))
+    });
+    game.clients_max_set(config.limits.max_players);
+    game.clients_per_address_set(config.limits.max_per_address);
+    game.motd_set(config.motd.clone());
+    game.spawn_set(config.spawn.clone());
+    game.corpse_set(config.corpse.clone());
+    game.food_set(config.food);
+    game.bots_set(config.bots.count);
+    game.auth_required_set(config.auth.required);
 
-    tokio::spawn(game_loop(game.clone(), Duration::from_millis(options.tick)));
+    let admin_socket = config.admin.socket.clone();
+    let http = config.http;
+    let (config, config_receiver) = watch::channel(config);
+    let config = Arc::new(config);
+    let game = actor::spawn(game, config_receiver);
+    tokio::spawn(reload_loop(options, config.clone()));
 
+    if let Some(address) = http {
+        let game = game.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            if let Err(e) = http::http_loop(address, game, config).await {
+                error!("Error in HTTP server: {}", e);
+            }
+        });
+    }
+
+    if let Some(path) = admin_socket.clone() {
+        let game = game.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            if let Err(e) = admin::admin_loop(path, game, config).await {
+                error!("Error in admin console: {}", e);
+            }
+        });
+    }
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    let (connection, connections) = mpsc::channel::<()>(1);
     loop {
-        let (stream, peer) = listener.accept().await?;
-        tokio::spawn(handler(game.clone(), stream, peer));
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => break,
+        };
+        let connection = connection.clone();
+        let game = game.clone();
+        let config = config.clone();
+        let leaderboard = leaderboard.clone();
+        let accounts = accounts.clone();
+        match &acceptor {
+            Some(acceptor) => {
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    match acceptor.accept(stream).await {
+                        Ok(stream) => {
+                            handler(game, config, leaderboard, accounts, stream, peer).await
+                        }
+                        Err(e) => warn!("TLS handshake with {} failed: {}", peer, e),
+                    }
+                    drop(connection);
+                });
+            }
+            None => {
+                tokio::spawn(async move {
+                    handler(game, config, leaderboard, accounts, stream, peer).await;
+                    drop(connection);
+                });
+            }
+        }
     }
 
+    // stop accepting connections while shutting down
+    drop(listener);
+    drop(connection);
+    let countdown = config.borrow().shutdown;
+    shutdown(&game, countdown, connections, leaderboard_task).await;
+
+    if let Some(path) = admin_socket {
+        let _ = std::fs::remove_file(path);
+    }
+
+    info!("Shut down");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a config file for a test.
+    fn config_file(name: &str, data: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "xenopeltis-config-{}-{}.toml",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    fn options(args: &[&str]) -> Options {
+        Options::from_iter_safe(std::iter::once("xenopeltis-server").chain(args.iter().copied()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn reject_sends_reason() {
+        let (server, client) = tokio::io::duplex(1024);
+        reject(server, DisconnectReason::ServerFull).await.unwrap();
+
+        let framed_reader = FramedRead::new(client, LengthDelimitedCodec::new());
+        let mut framed = SymmetricallyFramed::new(
+            framed_reader,
+            SymmetricalBincode::<ServerMessage>::default(),
+        );
+        match framed.try_next().await.unwrap() {
+            Some(ServerMessage::Disconnect(disconnect)) => {
+                assert_eq!(disconnect.reason, DisconnectReason::ServerFull)
+            }
+            message => panic!("Unexpected message {:?}", message),
+        }
+        // the connection is closed afterwards
+        assert!(framed.try_next().await.unwrap().is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_warns_and_disconnects() {
+        let config = Config {
+            tick: 60_000,
+            ..Config::default()
+        };
+        let (_config, receiver) = watch::channel(config);
+        let map = Map::new(5, 5).unwrap();
+        let game = actor::spawn(Game::new(map, mode::mode("classic").unwrap()), receiver);
+        let mut joined = game.join(([127, 0, 0, 1], 0).into()).await.unwrap();
+
+        // a connection that stays open holds up shutdown until the deadline
+        let (connection, connections) = mpsc::channel::<()>(1);
+        let start = tokio::time::Instant::now();
+        shutdown(&game, 2, connections, None).await;
+        assert_eq!(start.elapsed(), Duration::from_secs(2) + SHUTDOWN_DEADLINE);
+        drop(connection);
+
+        let mut countdown = vec![];
+        while let Ok(message) = joined.events.try_recv() {
+            if let ServerMessage::Shutdown(shutdown) = message {
+                countdown.push(shutdown.seconds);
+            }
+        }
+        assert_eq!(countdown, [2, 1]);
+
+        let mut reasons = vec![];
+        while let Ok(message) = joined.private.try_recv() {
+            if let ServerMessage::Disconnect(disconnect) = message {
+                reasons.push(disconnect.reason);
+            }
+        }
+        assert_eq!(reasons, [DisconnectReason::Shutdown]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_waits_for_leaderboard() {
+        let (_config, receiver) = watch::channel(Config::default());
+        let map = Map::new(5, 5).unwrap();
+        let game = actor::spawn(Game::new(map, mode::mode("classic").unwrap()), receiver);
+        let (connection, connections) = mpsc::channel::<()>(1);
+        drop(connection);
+        let leaderboard = tokio::spawn(tokio::time::sleep(Duration::from_secs(1)));
+
+        let start = tokio::time::Instant::now();
+        shutdown(&game, 0, connections, Some(leaderboard)).await;
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn options_override_file() {
+        let path = config_file(
+            "override",
+            "rows = 30\ncols = 50\n[limits]\nmax_players = 8\n",
+        );
+        let config = options(&[
+            "--config",
+            path.to_str().unwrap(),
+            "--rows",
+            "40",
+            "--max-players",
+            "2",
+            "--tls-certificate",
+            "cert.pem",
+            "--tls-key",
+            "key.pem",
+        ])
+        .config()
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.rows, 40);
+        assert_eq!(config.cols, 50);
+        assert_eq!(config.limits.max_players, 2);
+        assert_eq!(config.tls.unwrap().key, PathBuf::from("key.pem"));
+    }
+
+    #[test]
+    fn options_are_validated() {
+        assert!(options(&["--tick", "0"]).config().is_err());
+        assert!(options(&["--config", "/nonexistent/server.toml"])
+            .config()
+            .is_err());
+        // TLS needs both the certificate and the key
+        assert!(Options::from_iter_safe(["xenopeltis-server", "--tls-key", "key.pem"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn reload_on_hangup() {
+        let path = config_file("reload", "food = 2\n");
+        let options = options(&["--config", path.to_str().unwrap(), "--tick", "50"]);
+        let config = Arc::new(watch::channel(options.config().unwrap()).0);
+        let mut changes = config.subscribe();
+        tokio::spawn(reload_loop(options, config.clone()));
+        // give the loop time to catch the signal
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        std::fs::write(&path, "food = 7\n").unwrap();
+        let status = std::process::Command::new("kill")
+            .args(["-HUP", &std::process::id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        tokio::time::timeout(Duration::from_secs(5), changes.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // the command line still applies on top of the file
+        assert_eq!(config.borrow().food, 7);
+        assert_eq!(config.borrow().tick, 50);
+    }
+}
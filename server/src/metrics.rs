@@ -0,0 +1,237 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram, register_int_counter, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, Encoder, Histogram, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    TextEncoder,
+};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::AsyncWrite;
+use xenopeltis_common::*;
+use xenopeltis_game::{Stats, ROOM};
+
+pub static PLAYERS: Lazy<IntGauge> =
+    Lazy::new(|| register_int_gauge!("xenopeltis_players", "Connected players").unwrap());
+
+pub static TICKS: Lazy<IntCounter> =
+    Lazy::new(|| register_int_counter!("xenopeltis_ticks_total", "Game ticks run").unwrap());
+
+pub static TICK_DURATION: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "xenopeltis_tick_duration_seconds",
+        "Time taken to run a game tick",
+        vec![0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05]
+    )
+    .unwrap()
+});
+
+pub static MESSAGES_RECEIVED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "xenopeltis_messages_received_total",
+        "Messages received from clients",
+        &["type"]
+    )
+    .unwrap()
+});
+
+pub static MESSAGES_SENT: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "xenopeltis_messages_sent_total",
+        "Messages sent to clients",
+        &["type"]
+    )
+    .unwrap()
+});
+
+pub static BYTES_SENT: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!("xenopeltis_bytes_sent_total", "Bytes sent to clients").unwrap()
+});
+
+pub static BROADCAST_LAGGED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "xenopeltis_broadcast_lagged_total",
+        "Times a client fell behind and missed events"
+    )
+    .unwrap()
+});
+
+static ROOM_PLAYERS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!("xenopeltis_room_players", "Players in a room", &["room"]).unwrap()
+});
+
+static ROOM_SNAKES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "xenopeltis_room_snakes",
+        "Snakes alive in a room, including bots",
+        &["room"]
+    )
+    .unwrap()
+});
+
+static ROOM_FOOD: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!("xenopeltis_room_food", "Food on the board", &["room"]).unwrap()
+});
+
+static ROOM_LONGEST: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "xenopeltis_room_longest_snake",
+        "Length of the longest snake",
+        &["room"]
+    )
+    .unwrap()
+});
+
+static ROOM_PAUSED: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "xenopeltis_room_paused",
+        "Whether the game is paused",
+        &["room"]
+    )
+    .unwrap()
+});
+
+pub fn client_message_type(message: &ClientMessage) -> &'static str {
+    use ClientMessage::*;
+    match message {
+        Hello(_) => "hello",
+        Direction(_) => "direction",
+        Restart => "restart",
+        Quit => "quit",
+        Pause => "pause",
+        Leaderboard(_) => "leaderboard",
+        Admin(_) => "admin",
+        Register(_) => "register",
+        Login(_) => "login",
+        Token(_) => "token",
+        Forwarded(_) => "forwarded",
+    }
+}
+
+pub fn server_message_type(message: &ServerMessage) -> &'static str {
+    use ServerMessage::*;
+    match message {
+        FieldChange(_) => "field_change",
+        PlayerState(_) => "player_state",
+        Motd(_) => "motd",
+        Broadcast(_) => "broadcast",
+        Pause(_) => "pause",
+        Leaderboard(_) => "leaderboard",
+        Admin(_) => "admin",
+        Auth(_) => "auth",
+        Disconnect(_) => "disconnect",
+        Error(_) => "error",
+        Shutdown(_) => "shutdown",
+        PlayerDied(_) => "player_died",
+        Welcome(_) => "welcome",
+        Heads(_) => "heads",
+        PlayerJoined(_) => "player_joined",
+        Scores(_) => "scores",
+    }
+}
+
+/// Updates the metrics that are read from the game, and encodes all
+/// metrics in the Prometheus text format.
+pub fn render(stats: &Stats) -> String {
+    PLAYERS.set(stats.players as i64);
+    ROOM_PLAYERS
+        .with_label_values(&[ROOM])
+        .set(stats.players as i64);
+    ROOM_SNAKES
+        .with_label_values(&[ROOM])
+        .set(stats.snakes as i64);
+    ROOM_FOOD.with_label_values(&[ROOM]).set(stats.food as i64);
+    ROOM_LONGEST
+        .with_label_values(&[ROOM])
+        .set(stats.longest as i64);
+    ROOM_PAUSED
+        .with_label_values(&[ROOM])
+        .set(stats.paused as i64);
+
+    let mut buffer = vec![];
+    let encoder = TextEncoder::new();
+    // encoding into a vector can't fail
+    let _ = encoder.encode(&prometheus::gather(), &mut buffer);
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+/// Counts the bytes written to a client connection.
+pub struct CountingWriter<W> {
+    writer: W,
+}
+
+impl<W> CountingWriter<W> {
+    pub fn new(writer: W) -> Self {
+        CountingWriter { writer }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for CountingWriter<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.writer).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = result {
+            BYTES_SENT.inc_by(written as u64);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[test]
+    fn message_types() {
+        assert_eq!(client_message_type(&ClientMessage::Restart), "restart");
+        let address = ([127, 0, 0, 1], 0).into();
+        assert_eq!(
+            client_message_type(&ClientMessage::Forwarded(ForwardedMessage { address })),
+            "forwarded"
+        );
+        let shutdown = ServerMessage::Shutdown(ShutdownMessage { seconds: 1 });
+        assert_eq!(server_message_type(&shutdown), "shutdown");
+    }
+
+    #[test]
+    fn render_room_stats() {
+        let stats = Stats {
+            players: 3,
+            snakes: 4,
+            food: 5,
+            longest: 6,
+            paused: true,
+        };
+        let text = render(&stats);
+        assert!(text.contains("xenopeltis_players 3\n"));
+        assert!(text.contains(&format!("xenopeltis_room_snakes{{room=\"{}\"}} 4\n", ROOM)));
+        assert!(text.contains(&format!("xenopeltis_room_food{{room=\"{}\"}} 5\n", ROOM)));
+        assert!(text.contains(&format!(
+            "xenopeltis_room_longest_snake{{room=\"{}\"}} 6\n",
+            ROOM
+        )));
+        assert!(text.contains(&format!("xenopeltis_room_paused{{room=\"{}\"}} 1\n", ROOM)));
+    }
+
+    #[tokio::test]
+    async fn counting_writer() {
+        let before = BYTES_SENT.get();
+        let mut writer = CountingWriter::new(vec![]);
+        writer.write_all(b"hello").await.unwrap();
+        assert_eq!(writer.writer, b"hello");
+        // other tests may send too, but never take bytes away
+        assert!(BYTES_SENT.get() >= before + 5);
+    }
+}
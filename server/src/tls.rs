@@ -0,0 +1,137 @@
+use crate::config::Tls;
+use anyhow::{anyhow, Context, Result};
+use rustls_pemfile::Item;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+use tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+fn certificates(path: &Path) -> Result<Vec<Certificate>> {
+    let mut reader = BufReader::new(
+        File::open(path).with_context(|| format!("Reading certificate {}", path.display()))?,
+    );
+    let certificates: Vec<_> = rustls_pemfile::certs(&mut reader)?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    if certificates.is_empty() {
+        return Err(anyhow!("No certificates in {}", path.display()));
+    }
+    Ok(certificates)
+}
+
+fn key(path: &Path) -> Result<PrivateKey> {
+    let mut reader = BufReader::new(
+        File::open(path).with_context(|| format!("Reading key {}", path.display()))?,
+    );
+    for item in rustls_pemfile::read_all(&mut reader)? {
+        match item {
+            Item::RSAKey(key) | Item::PKCS8Key(key) | Item::ECKey(key) => {
+                return Ok(PrivateKey(key))
+            }
+            _ => {}
+        }
+    }
+    Err(anyhow!("No private key in {}", path.display()))
+}
+
+/// Builds the acceptor for TLS connections from the certificate chain and key.
+pub fn acceptor(tls: &Tls) -> Result<TlsAcceptor> {
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certificates(&tls.certificate)?, key(&tls.key)?)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::rustls::{ClientConfig, RootCertStore, ServerName};
+    use tokio_rustls::TlsConnector;
+
+    /// Writes a self-signed certificate for localhost and its key, returns
+    /// the certificate for clients to trust.
+    fn self_signed(name: &str) -> (Tls, Certificate) {
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let path = |kind| {
+            std::env::temp_dir().join(format!(
+                "xenopeltis-tls-{}-{}-{}.pem",
+                std::process::id(),
+                name,
+                kind
+            ))
+        };
+        let tls = Tls {
+            certificate: path("certificate"),
+            key: path("key"),
+        };
+        std::fs::write(&tls.certificate, generated.serialize_pem().unwrap()).unwrap();
+        std::fs::write(&tls.key, generated.serialize_private_key_pem()).unwrap();
+        (tls, Certificate(generated.serialize_der().unwrap()))
+    }
+
+    fn remove(tls: &Tls) {
+        std::fs::remove_file(&tls.certificate).unwrap();
+        std::fs::remove_file(&tls.key).unwrap();
+    }
+
+    #[tokio::test]
+    async fn acceptor_serves_certificate() {
+        let (tls, certificate) = self_signed("serve");
+        let acceptor = acceptor(&tls).unwrap();
+        remove(&tls);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = acceptor.accept(stream).await.unwrap();
+            stream.write_all(b"snake").await.unwrap();
+            stream.flush().await.unwrap();
+        });
+
+        let mut roots = RootCertStore::empty();
+        roots.add(&certificate).unwrap();
+        let config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let stream = TcpStream::connect(address).await.unwrap();
+        let name = ServerName::try_from("localhost").unwrap();
+        let mut stream = TlsConnector::from(Arc::new(config))
+            .connect(name, stream)
+            .await
+            .unwrap();
+        let mut data = [0; 5];
+        stream.read_exact(&mut data).await.unwrap();
+        assert_eq!(&data, b"snake");
+        server.await.unwrap();
+    }
+
+    #[test]
+    fn acceptor_errors() {
+        let (tls, _) = self_signed("errors");
+
+        // the certificate has no key in it
+        let swapped = Tls {
+            certificate: tls.certificate.clone(),
+            key: tls.certificate.clone(),
+        };
+        assert!(acceptor(&swapped).is_err());
+
+        // nor does the key have a certificate
+        let swapped = Tls {
+            certificate: tls.key.clone(),
+            key: tls.key.clone(),
+        };
+        assert!(acceptor(&swapped).is_err());
+
+        remove(&tls);
+        assert!(acceptor(&tls).is_err());
+    }
+}
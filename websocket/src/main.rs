@@ -51,6 +51,11 @@ async fn handle_connection_real(
     );
     info!("Connected to server for {}", peer);
 
+    // the server counts the client, not us, if it trusts us
+    messages
+        .send(ClientMessage::Forwarded(ForwardedMessage { address: peer }))
+        .await?;
+
     loop {
         select! {
             message = messages.next() => {
@@ -1,5 +1,4 @@
 use yew::prelude::*;
-use yew::virtual_dom::VChild;
 
 pub enum Msg {
     AddOne,
@@ -10,9 +9,6 @@ pub struct Launcher {
     value: i64,
 }
 
-#[derive(Properties, PartialEq)]
-pub struct Props {}
-
 impl Component for Launcher {
     type Message = Msg;
     type Properties = ();
@@ -30,7 +26,6 @@ impl Component for Launcher {
                 true
             }
             Msg::Connect => true,
-            _ => false,
         }
     }
 
@@ -1,7 +1,6 @@
 mod launcher;
 
 use launcher::Launcher;
-use yew::prelude::*;
 
 fn main() {
     yew::start_app::<Launcher>();